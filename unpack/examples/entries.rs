@@ -0,0 +1,16 @@
+use std::path::Path;
+
+use deep_unpack::UnpackLevel;
+
+fn main() {
+    let extract_file = Path::new("tests").join("mocks").join("multiple");
+    let entries = deep_unpack::DeepWalk::new()
+        .folder(format!("{}", extract_file.display()))
+        .unpack_level(UnpackLevel::Exhausted)
+        .entries()
+        .unwrap();
+
+    for (virtual_path, metadata, _reader) in entries.take(3) {
+        println!("{} (level {})", virtual_path, metadata.depth);
+    }
+}
@@ -6,7 +6,7 @@ fn main() {
     let result = deep_unpack::DeepWalk::new()
         .folder(format!("{}", extract_file.display()))
         .unpack_folder(format!("{}", destination_folder.display()))
-        .unpack_level(4 as u32)
+        .unpack_level(4_u32)
         .extract_template("_PREFIX_${FILENAME}$_SUFFIX_")
         .extract()
         .unwrap();
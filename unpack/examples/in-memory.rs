@@ -0,0 +1,17 @@
+use std::path::Path;
+
+use deep_unpack::UnpackLevel;
+
+fn main() {
+    let extract_file = Path::new("tests").join("mocks").join("deep");
+    let destination_folder = Path::new("tmp").join("extract").join("in-memory");
+    let result = deep_unpack::DeepWalk::new()
+        .folder(format!("{}", extract_file.display()))
+        .unpack_folder(format!("{}", destination_folder.display()))
+        .unpack_level(UnpackLevel::Exhausted)
+        .in_memory_limit(1024 * 1024_u64)
+        .extract()
+        .unwrap();
+
+    println!("{:#?}", result);
+}
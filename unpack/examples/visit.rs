@@ -0,0 +1,32 @@
+use std::{
+    io::{self, Read},
+    path::Path,
+};
+
+use deep_unpack::{EntryMetadata, UnpackLevel, VirtualPath, Visitor};
+
+struct PrintVisitor;
+
+impl Visitor for PrintVisitor {
+    fn on_entry(
+        &self,
+        virtual_path: &VirtualPath,
+        _metadata: &EntryMetadata,
+        reader: &mut dyn Read,
+    ) -> anyhow::Result<()> {
+        let bytes = io::copy(reader, &mut io::sink())?;
+        println!("{} ({} bytes)", virtual_path, bytes);
+        Ok(())
+    }
+}
+
+fn main() {
+    let extract_file = Path::new("tests").join("mocks").join("deep");
+    let result = deep_unpack::DeepWalk::new()
+        .folder(format!("{}", extract_file.display()))
+        .unpack_level(UnpackLevel::Exhausted)
+        .visit(&PrintVisitor)
+        .unwrap();
+
+    println!("{:#?}", result);
+}
@@ -0,0 +1,258 @@
+//! Cache of the unpacked archives across runs.
+//!
+//! An entry is keyed by the archive content and the configuration which
+//! shapes the output, and records the extract folder and the files written to
+//! it. An archive is not unpacked again while its recorded files are intact.
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    data::RewrittenEntry,
+    manifest::{modified, ManifestEntry},
+};
+
+/// Cache entry of an unpacked archive
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CacheEntry {
+    pub extract_to: PathBuf,
+    pub entries_extracted: u64,
+    pub entries_skipped: u64,
+    pub uncompressed_bytes: u64,
+    pub rewritten_entries: Vec<RewrittenEntry>,
+    /// the files written to the extract folder, their nested archives have
+    /// their own entries
+    pub files: Vec<ManifestEntry>,
+}
+
+impl CacheEntry {
+    /// Check that the extract folder is the same and that the files were not
+    /// removed or modified since they were written, by their size and their
+    /// modification time to the nanosecond
+    fn is_valid(&self, extract_to: &Path) -> bool {
+        self.extract_to == extract_to
+            && self.files.iter().all(|file| {
+                fs::metadata(&file.path).is_ok_and(|metadata| {
+                    let mtime = modified(&metadata);
+                    metadata.is_file()
+                        && metadata.len() == file.size
+                        && file.mtime == mtime.map(|elapsed| elapsed.as_secs())
+                        && file.mtime_nanos == mtime.map(|elapsed| elapsed.subsec_nanos())
+                })
+            })
+    }
+}
+
+/// On-disk cache of the unpacked archives, given to
+/// `DeepWalk::new().cache_folder(..)`
+#[derive(Debug, Clone)]
+pub struct Cache {
+    folder: PathBuf,
+}
+
+impl Cache {
+    pub fn new<P: Into<PathBuf>>(folder: P) -> Self {
+        Self {
+            folder: folder.into(),
+        }
+    }
+
+    /// The folder of the cache entries
+    #[must_use]
+    pub fn folder(&self) -> &Path {
+        &self.folder
+    }
+
+    /// The valid entry of the key, an invalid entry is removed
+    pub(crate) fn get(&self, key: &str, extract_to: &Path) -> Option<CacheEntry> {
+        let path = self.entry_path(key);
+        let entry = File::open(&path)
+            .ok()
+            .and_then(|file| serde_json::from_reader::<_, CacheEntry>(BufReader::new(file)).ok())?;
+        if !entry.is_valid(extract_to) {
+            log::debug!("invalidate cache entry: {}", path.display());
+            if let Err(e) = fs::remove_file(&path) {
+                log::debug!(
+                    "could not remove cache entry: {}. err: {}",
+                    path.display(),
+                    e
+                );
+            }
+            return None;
+        }
+
+        // the entry was used, it is not pruned
+        if let Err(e) = File::options()
+            .append(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(SystemTime::now()))
+        {
+            log::debug!(
+                "could not touch cache entry: {}. err: {}",
+                path.display(),
+                e
+            );
+        }
+        Some(entry)
+    }
+
+    /// Record the entry of the key, the entry is written whole or not at all
+    ///
+    /// # Errors
+    /// When the entry could not be written
+    pub(crate) fn put(&self, key: &str, entry: &CacheEntry) -> io::Result<()> {
+        fs::create_dir_all(&self.folder)?;
+        let path = self.entry_path(key);
+        let partial = path.with_extension("partial");
+        let mut writer = BufWriter::new(File::create(&partial)?);
+        serde_json::to_writer(&mut writer, entry)?;
+        writer.flush()?;
+        drop(writer);
+        fs::rename(partial, path)
+    }
+
+    /// Remove every entry, the archives are unpacked again by the next run
+    ///
+    /// # Errors
+    /// When an entry could not be removed
+    pub fn clear(&self) -> io::Result<()> {
+        self.prune(Duration::ZERO).map(|_| ())
+    }
+
+    /// Remove the entries which were not used for the given duration, e.g.
+    /// `Duration::from_secs(days * 24 * 60 * 60)`. Returns the number of
+    /// removed entries.
+    ///
+    /// # Errors
+    /// When the folder could not be read or an entry could not be removed
+    pub fn prune(&self, older_than: Duration) -> io::Result<usize> {
+        let dir = match fs::read_dir(&self.folder) {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        let now = SystemTime::now();
+        let mut removed = 0;
+        for entry in dir {
+            let entry = entry?;
+            let path = entry.path();
+            if !matches!(
+                path.extension().and_then(|e| e.to_str()),
+                Some("json" | "partial")
+            ) {
+                continue;
+            }
+            let used = entry.metadata()?.modified()?;
+            if now.duration_since(used).unwrap_or_default() >= older_than {
+                fs::remove_file(path)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.folder.join(format!("{}.json", key))
+    }
+}
+
+#[cfg(test)]
+mod test_cache {
+
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+
+    fn temp_folder() -> PathBuf {
+        let folder = std::env::temp_dir().join(format!("deep-unpack-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&folder).unwrap();
+        folder
+    }
+
+    #[test]
+    fn can_invalidate_modified_files() {
+        let folder = temp_folder();
+        let file = folder.join("out").join("kestrel.txt");
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(&file, "kestrel").unwrap();
+        let mtime = modified(&fs::metadata(&file).unwrap()).unwrap();
+
+        let cache = Cache::new(folder.join("cache"));
+        let entry = CacheEntry {
+            extract_to: folder.join("out"),
+            entries_extracted: 1,
+            entries_skipped: 0,
+            uncompressed_bytes: 7,
+            rewritten_entries: vec![],
+            files: vec![ManifestEntry {
+                virtual_path: "a.zip!/kestrel.txt".parse().unwrap(),
+                path: file.clone(),
+                size: 7,
+                mode: None,
+                mtime: Some(mtime.as_secs()),
+                mtime_nanos: Some(mtime.subsec_nanos()),
+                sha256: String::new(),
+                blake3: None,
+            }],
+        };
+        cache.put("key", &entry).unwrap();
+
+        assert!(cache.get("key", &folder.join("other")).is_none());
+        cache.put("key", &entry).unwrap();
+        assert!(cache.get("key", &folder.join("out")).is_some());
+
+        fs::write(&file, "kestrels").unwrap();
+        assert!(cache.get("key", &folder.join("out")).is_none());
+        // the invalid entry was removed
+        assert!(!cache.folder().join("key.json").exists());
+
+        // the same size, modified within the same second
+        fs::write(&file, "kestrel").unwrap();
+        cache.put("key", &entry).unwrap();
+        fs::write(&file, "kestrei").unwrap();
+        File::options()
+            .append(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(UNIX_EPOCH + mtime + Duration::from_nanos(1))
+            .unwrap();
+        assert!(cache.get("key", &folder.join("out")).is_none());
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn can_prune_unused_entries() {
+        let folder = temp_folder();
+        let cache = Cache::new(&folder);
+        let entry = CacheEntry {
+            extract_to: folder.join("out"),
+            entries_extracted: 0,
+            entries_skipped: 0,
+            uncompressed_bytes: 0,
+            rewritten_entries: vec![],
+            files: vec![],
+        };
+        cache.put("old", &entry).unwrap();
+        cache.put("new", &entry).unwrap();
+        File::options()
+            .append(true)
+            .open(folder.join("old.json"))
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(3 * 24 * 60 * 60))
+            .unwrap();
+
+        assert_eq!(
+            cache.prune(Duration::from_secs(2 * 24 * 60 * 60)).unwrap(),
+            1
+        );
+        assert!(folder.join("new.json").exists());
+        cache.clear().unwrap();
+        assert!(!folder.join("new.json").exists());
+        fs::remove_dir_all(folder).unwrap();
+    }
+}
@@ -1,7 +1,15 @@
-use std::path::PathBuf;
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use regex::Regex;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::UnpackError, formats::kinds::ArchiveKind, manifest::Manifest, virtual_path::VirtualPath,
+};
 
 #[derive(Deserialize, Default, Debug, Clone)]
 pub struct NoWalkList {
@@ -9,9 +17,240 @@ pub struct NoWalkList {
     pub ignores: Vec<Regex>,
 }
 
-#[derive(Debug, Clone)]
+/// How archive files are detected while walking the folder
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum DetectionMode {
+    /// By the file name extension. Self-extracting archives (e.g. a
+    /// `setup.exe`) have no archive extension, the `.exe`, `.sh` and `.bin`
+    /// files are searched for a payload
+    #[default]
+    Extension,
+    /// By the file header (magic bytes), whatever the file name is
+    Content,
+    /// By the file name extension, confirmed or corrected by the file header
+    Both,
+}
+
+/// How deep nested archives are unpacked
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum UnpackLevel {
+    /// Unpack nested archives up to the given depth, `1` unpacks only the
+    /// archives found in the folder
+    Depth(u32),
+    /// Unpack newly found archives until none remain, up to
+    /// [`UnpackLevel::MAX_DEPTH`]
+    Exhausted,
+}
+
+impl UnpackLevel {
+    /// Safety cap of the nesting depth with [`UnpackLevel::Exhausted`]
+    pub const MAX_DEPTH: u32 = 64;
+
+    /// The deepest level to unpack
+    #[must_use]
+    pub fn max_depth(self) -> u32 {
+        match self {
+            Self::Depth(depth) => depth,
+            Self::Exhausted => Self::MAX_DEPTH,
+        }
+    }
+}
+
+impl Default for UnpackLevel {
+    fn default() -> Self {
+        Self::Depth(1)
+    }
+}
+
+impl From<u32> for UnpackLevel {
+    fn from(depth: u32) -> Self {
+        Self::Depth(depth)
+    }
+}
+
+/// What to do with archive entries which their path escapes the destination
+/// directory (`..` components, absolute paths, drive letters and UNC prefixes)
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum PathPolicy {
+    /// Fail the archive unpacking
+    #[default]
+    Reject,
+    /// Drop the escaping components and extract the entry inside the
+    /// destination directory
+    Sanitize,
+    /// Skip the entry and unpack the rest of the archive
+    SkipEntry,
+}
+
+/// What to do with archives which their content was already unpacked in the
+/// run. Except with [`DuplicateMode::Unpack`], only the first copy is
+/// unpacked: the copy found at the lowest depth, then with the lowest virtual
+/// path
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum DuplicateMode {
+    /// Record the extract folder of the first copy in the status
+    #[default]
+    Record,
+    /// Mirror the extract folder of the first copy with hard links to its
+    /// files
+    Hardlink,
+    /// Link the extract folder to the extract folder of the first copy
+    Symlink,
+    /// Unpack every copy, only an archive found in a copy of itself is
+    /// skipped and records the copy it was found in
+    Unpack,
+}
+
+/// Archive entry which its path was rewritten or skipped by the [`PathPolicy`]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RewrittenEntry {
+    /// The entry name in the archive
+    pub entry: String,
+    /// The path relative to the destination directory, `None` when skipped
+    pub path: Option<PathBuf>,
+}
+
+/// Limits against decompression bombs, enforced while the entries are written.
+/// `None` is unlimited.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum uncompressed bytes of a single archive
+    pub max_archive_bytes: Option<u64>,
+    /// Maximum uncompressed bytes of all the archives of the run
+    pub max_total_bytes: Option<u64>,
+    /// Maximum number of entries of a single archive
+    pub max_entries: Option<u64>,
+    /// Maximum ratio between the uncompressed bytes and the archive file size
+    pub max_ratio: Option<u64>,
+    /// Maximum uncompressed bytes of a single entry
+    pub max_entry_bytes: Option<u64>,
+}
+
+/// The limit which stopped the unpacking of an archive, with its configured
+/// value
+#[derive(Serialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LimitExceeded {
+    ArchiveBytes(u64),
+    TotalBytes(u64),
+    Entries(u64),
+    Ratio(u64),
+    EntryBytes(u64),
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ArchiveBytes(limit) => {
+                write!(f, "archive uncompressed size exceeds {} bytes", limit)
+            }
+            Self::TotalBytes(limit) => write!(f, "total uncompressed size exceeds {} bytes", limit),
+            Self::Entries(limit) => write!(f, "archive has more than {} entries", limit),
+            Self::Ratio(limit) => write!(f, "archive compression ratio exceeds {}", limit),
+            Self::EntryBytes(limit) => write!(f, "entry uncompressed size exceeds {} bytes", limit),
+        }
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+#[derive(Serialize, Debug, Clone)]
 pub struct UnpackStatus {
     pub archive_file: PathBuf,
+    /// The archive path through its containers, the `archive_file` of a root
+    /// archive
+    pub virtual_path: VirtualPath,
+    pub kind: ArchiveKind,
+    /// Unpack level of the archive, archives of the folder are level 1
+    pub depth: u32,
+    /// Virtual path of the archive it was found in, `None` for the archives
+    /// of the folder
+    pub parent: Option<VirtualPath>,
+    /// Extract folder of the archive, also set with an `err` when some
+    /// entries were written before the failure
     pub extract_to: Option<PathBuf>,
-    pub err: Option<String>,
+    pub err: Option<UnpackError>,
+    pub rewritten_entries: Vec<RewrittenEntry>,
+    /// The limit which stopped the unpacking, the archive may be a
+    /// decompression bomb
+    pub limit_exceeded: Option<LimitExceeded>,
+    /// The archive with the same content which was already unpacked in the
+    /// run, this archive is not unpacked again
+    pub duplicate_of: Option<PathBuf>,
+    /// Extract folder of the `duplicate_of` archive, the `extract_to` of a
+    /// duplicate is the link to it with [`DuplicateMode::Hardlink`] and
+    /// [`DuplicateMode::Symlink`]
+    pub canonical_extract_to: Option<PathBuf>,
+    /// The extract folder of a previous run was reused, see
+    /// [`Cache`](crate::Cache)
+    pub cached: bool,
+    /// File entries written, visited or kept in memory
+    pub entries_extracted: u64,
+    /// Entries skipped by the [`PathPolicy`] or by the archive format (links,
+    /// special files, unsupported entries)
+    pub entries_skipped: u64,
+    /// Size of the archive file
+    pub compressed_bytes: u64,
+    /// Uncompressed bytes of the entries, up to the failure or the exceeded
+    /// limit
+    pub uncompressed_bytes: u64,
+    /// Time spent unpacking the archive, without its nested archives
+    pub elapsed: Duration,
+}
+
+/// Archive found in the run, with the archives found in it
+#[derive(Serialize, Debug, Clone)]
+pub struct ArchiveNode {
+    pub archive_file: PathBuf,
+    pub virtual_path: VirtualPath,
+    pub kind: ArchiveKind,
+    /// unpack level of the archive, archives of the folder are level 1
+    pub depth: u32,
+    /// Virtual path of the archive it was found in, `None` for the archives
+    /// of the folder
+    pub parent: Option<VirtualPath>,
+    /// Extract folder of the archive, nested archives unpacked from memory
+    /// have one too. `None` when the archive failed before writing an entry,
+    /// was visited or is a duplicate which is not linked, see [`DuplicateMode`]
+    pub extract_to: Option<PathBuf>,
+    /// Archives found in this archive, by virtual path
+    pub children: Vec<ArchiveNode>,
+}
+
+/// Result of an unpacking run
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct UnpackResult {
+    /// Status of every archive found in the run
+    pub statuses: Vec<UnpackStatus>,
+    /// The archives of the folder, each with the archives found in it
+    pub tree: Vec<ArchiveNode>,
+    /// The deepest nesting level reached, `1` when only the archives of the
+    /// folder were unpacked and `0` when no archive was found
+    pub max_depth: u32,
+    /// The files written by the run, with [`DeepWalk::manifest`](crate::DeepWalk::manifest)
+    pub manifest: Option<Manifest>,
+}
+
+impl UnpackResult {
+    /// Virtual path of an extracted file or folder, from the archive which was
+    /// extracted to the innermost folder containing it. `None` when the path
+    /// is not under an extract folder.
+    #[must_use]
+    pub fn virtual_path<P: AsRef<Path>>(&self, path: P) -> Option<VirtualPath> {
+        let path = path.as_ref();
+        self.statuses
+            .iter()
+            .filter_map(|status| {
+                let extract_to = status.extract_to.as_ref()?;
+                let member = path.strip_prefix(extract_to).ok()?;
+                Some((extract_to.components().count(), status, member))
+            })
+            .max_by_key(|(len, _, _)| *len)
+            .map(|(_, status, member)| {
+                if member.as_os_str().is_empty() {
+                    status.virtual_path.clone()
+                } else {
+                    status.virtual_path.join(member)
+                }
+            })
+    }
 }
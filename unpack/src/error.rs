@@ -0,0 +1,277 @@
+//! Errors of the unpacking, per archive and per run
+use std::{error::Error, fmt, io, sync::Arc};
+
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+
+use crate::data::LimitExceeded;
+
+/// Cause of an error, shared so the statuses can be cloned. Its `Debug` is
+/// the message.
+#[derive(Clone)]
+pub struct ErrorSource(Arc<dyn Error + Send + Sync>);
+
+impl ErrorSource {
+    /// Source of a message without a cause
+    pub fn msg<M: fmt::Display + fmt::Debug + Send + Sync + 'static>(message: M) -> Self {
+        Self::from(anyhow::Error::msg(message))
+    }
+
+    /// The wrapped error, for downcasting
+    #[must_use]
+    pub fn get_ref(&self) -> &(dyn Error + Send + Sync + 'static) {
+        self.0.as_ref()
+    }
+}
+
+impl From<anyhow::Error> for ErrorSource {
+    fn from(err: anyhow::Error) -> Self {
+        Self(Arc::from(Box::<dyn Error + Send + Sync>::from(err)))
+    }
+}
+
+impl fmt::Debug for ErrorSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.0.to_string(), f)
+    }
+}
+
+impl fmt::Display for ErrorSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl Error for ErrorSource {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.0.source()
+    }
+}
+
+/// Why an archive could not be unpacked, reported in its
+/// [`UnpackStatus`](crate::UnpackStatus)
+#[derive(Debug, Clone)]
+pub enum UnpackError {
+    /// The archive is malformed or truncated
+    Corrupt(ErrorSource),
+    /// The archive or some of its entries are encrypted
+    Encrypted(ErrorSource),
+    /// The archive uses a format, a compression method or an entry type which
+    /// is not supported
+    Unsupported(ErrorSource),
+    /// An entry escapes the destination directory, with
+    /// [`PathPolicy::Reject`](crate::PathPolicy::Reject)
+    UnsafePath(String),
+    /// A limit stopped the unpacking, the archive may be a decompression bomb
+    LimitExceeded(LimitExceeded),
+    /// Reading the archive or writing its entries failed
+    Io(ErrorSource),
+    /// The [`Visitor`](crate::Visitor) returned an error
+    Visitor(ErrorSource),
+    Other(ErrorSource),
+}
+
+impl UnpackError {
+    pub(crate) fn corrupt<M: fmt::Display + fmt::Debug + Send + Sync + 'static>(
+        message: M,
+    ) -> Self {
+        Self::Corrupt(ErrorSource::msg(message))
+    }
+
+    #[cfg(feature = "rar")]
+    pub(crate) fn encrypted<M: fmt::Display + fmt::Debug + Send + Sync + 'static>(
+        message: M,
+    ) -> Self {
+        Self::Encrypted(ErrorSource::msg(message))
+    }
+
+    pub(crate) fn unsupported<M: fmt::Display + fmt::Debug + Send + Sync + 'static>(
+        message: M,
+    ) -> Self {
+        Self::Unsupported(ErrorSource::msg(message))
+    }
+}
+
+impl fmt::Display for UnpackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Corrupt(source)
+            | Self::Encrypted(source)
+            | Self::Unsupported(source)
+            | Self::Io(source)
+            | Self::Visitor(source)
+            | Self::Other(source) => write!(f, "{}", source),
+            Self::UnsafePath(entry) => {
+                write!(f, "entry `{}` escapes the destination directory", entry)
+            }
+            Self::LimitExceeded(limit) => write!(f, "{}", limit),
+        }
+    }
+}
+
+impl Error for UnpackError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Corrupt(source)
+            | Self::Encrypted(source)
+            | Self::Unsupported(source)
+            | Self::Io(source)
+            | Self::Visitor(source)
+            | Self::Other(source) => source.source(),
+            Self::UnsafePath(_) | Self::LimitExceeded(_) => None,
+        }
+    }
+}
+
+/// Serialized as its variant name and its display string
+impl Serialize for UnpackError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let kind = match self {
+            Self::Corrupt(_) => "Corrupt",
+            Self::Encrypted(_) => "Encrypted",
+            Self::Unsupported(_) => "Unsupported",
+            Self::UnsafePath(_) => "UnsafePath",
+            Self::LimitExceeded(_) => "LimitExceeded",
+            Self::Io(_) => "Io",
+            Self::Visitor(_) => "Visitor",
+            Self::Other(_) => "Other",
+        };
+        let mut state = serializer.serialize_struct("UnpackError", 2)?;
+        state.serialize_field("kind", kind)?;
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
+    }
+}
+
+impl From<anyhow::Error> for UnpackError {
+    /// Classify an error of the archive formats by its causes
+    fn from(err: anyhow::Error) -> Self {
+        if let Some(e) = err.downcast_ref::<Self>() {
+            return e.clone();
+        }
+        if let Some(limit) = err.downcast_ref::<LimitExceeded>() {
+            return Self::LimitExceeded(*limit);
+        }
+
+        let variant = err.chain().find_map(classify).unwrap_or(Self::Other);
+        variant(ErrorSource::from(err))
+    }
+}
+
+/// Variant of an error of the archive libraries
+fn classify(cause: &(dyn Error + 'static)) -> Option<fn(ErrorSource) -> UnpackError> {
+    use sevenz_rust::Error as SevenZError;
+    use zip::result::ZipError;
+
+    if let Some(e) = cause.downcast_ref::<ZipError>() {
+        return Some(match e {
+            ZipError::Io(e) => io_error_variant(e),
+            ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED) => UnpackError::Encrypted,
+            ZipError::UnsupportedArchive(_) => UnpackError::Unsupported,
+            ZipError::InvalidArchive(_) | ZipError::FileNotFound => UnpackError::Corrupt,
+        });
+    }
+    if let Some(e) = cause.downcast_ref::<SevenZError>() {
+        return match e {
+            SevenZError::PasswordRequired | SevenZError::MaybeBadPassword(_) => {
+                Some(UnpackError::Encrypted)
+            }
+            SevenZError::UnsupportedVersion { .. }
+            | SevenZError::ExternalUnsupported
+            | SevenZError::UnsupportedCompressionMethod(_)
+            | SevenZError::MaxMemLimited { .. }
+            | SevenZError::Unsupported(_) => Some(UnpackError::Unsupported),
+            SevenZError::Io(e, _) | SevenZError::FileOpen(e, _) => Some(io_error_variant(e)),
+            SevenZError::Other(_) => None,
+            _ => Some(UnpackError::Corrupt),
+        };
+    }
+    cause.downcast_ref::<io::Error>().map(io_error_variant)
+}
+
+/// Malformed data is reported as an I/O error by most decoders
+fn io_error_variant(err: &io::Error) -> fn(ErrorSource) -> UnpackError {
+    match err.kind() {
+        io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => UnpackError::Corrupt,
+        _ => UnpackError::Io,
+    }
+}
+
+/// Why a run could not start or finish, the errors of the archives are
+/// reported in their statuses
+#[derive(Debug)]
+pub enum RunError {
+    /// The configuration is invalid
+    Config(ErrorSource),
+    /// The background unpacking of [`Entries`](crate::Entries) panicked
+    Panicked,
+    /// The manifest file could not be written
+    Manifest(ErrorSource),
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Config(source) => write!(f, "invalid configuration: {}", source),
+            Self::Panicked => write!(f, "unpacking the entries panicked"),
+            Self::Manifest(source) => write!(f, "could not write the manifest: {}", source),
+        }
+    }
+}
+
+impl Error for RunError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Config(source) | Self::Manifest(source) => source.source(),
+            Self::Panicked => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_error {
+
+    use anyhow::Context;
+    use insta::assert_debug_snapshot;
+
+    use super::*;
+
+    #[test]
+    fn can_classify_errors() {
+        let errors = [
+            anyhow::Error::new(zip::result::ZipError::UnsupportedArchive(
+                zip::result::ZipError::PASSWORD_REQUIRED,
+            )),
+            anyhow::Error::new(zip::result::ZipError::InvalidArchive("Invalid zip header")),
+            anyhow::Error::new(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated")),
+            anyhow::Error::new(LimitExceeded::Entries(2)),
+            UnpackError::unsupported("unsupported cpio format").into(),
+            anyhow::anyhow!("could not get file name"),
+        ]
+        .into_iter()
+        .map(|err| {
+            let err = UnpackError::from(err);
+            (err.to_string(), err)
+        })
+        .collect::<Vec<_>>();
+
+        assert_debug_snapshot!(errors);
+    }
+
+    #[test]
+    fn can_chain_sources() {
+        let err = Err::<(), _>(io::Error::new(io::ErrorKind::NotFound, "gone"))
+            .context("could not open rar volume fernhollow.part2.rar")
+            .unwrap_err();
+        let err = UnpackError::from(err);
+
+        assert!(matches!(err, UnpackError::Io(_)));
+        assert_eq!(
+            err.to_string(),
+            "could not open rar volume fernhollow.part2.rar"
+        );
+        assert_eq!(
+            err.source().map(ToString::to_string),
+            Some("gone".to_string())
+        );
+    }
+}
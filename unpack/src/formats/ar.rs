@@ -0,0 +1,47 @@
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use ar::Archive as ArArchiveReader;
+
+use crate::formats::{kinds::Archive, path::Destination};
+
+/// Unix `ar` archive, the container of debian packages (`.deb`)
+#[derive(Debug)]
+pub struct ArArchive {
+    path: PathBuf,
+}
+
+impl ArArchive {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+}
+
+impl Archive for ArArchive {
+    ///  Get the the archive file path
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// unpack ar file to destination directory
+    fn unpack_to(&mut self, destination: &mut Destination) -> Result<()> {
+        unpack_ar(BufReader::new(File::open(&self.path)?), destination)
+    }
+}
+
+/// unpack an ar stream to destination directory
+pub fn unpack_ar<R: Read>(reader: R, destination: &mut Destination) -> Result<()> {
+    let mut rdr = ArArchiveReader::new(reader);
+    while let Some(entry) = rdr.next_entry() {
+        let mut entry = entry?;
+        let name = String::from_utf8_lossy(entry.header().identifier()).to_string();
+        destination.write(&name, &mut entry)?;
+    }
+    Ok(())
+}
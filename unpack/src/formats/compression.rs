@@ -0,0 +1,71 @@
+//! Compression codecs that wrap an archive stream
+use std::io::Read;
+
+use anyhow::Result;
+use bzip2::read::BzDecoder;
+use flate2::read::MultiGzDecoder;
+use lz4_flex::frame::FrameDecoder as Lz4Decoder;
+use xz2::{read::XzDecoder, stream::Stream as XzStream};
+use zstd::stream::read::Decoder as ZstdDecoder;
+
+use crate::formats::lzw::LzwDecoder;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Bzip2,
+    Xz,
+    Zstd,
+    Lz4,
+    Lzma,
+    Lzw,
+}
+
+impl Compression {
+    /// Wrap the given reader with a decoder of the compression format
+    pub fn decoder<'a, R: Read + 'a>(self, reader: R) -> Result<Box<dyn Read + 'a>> {
+        Ok(match self {
+            Self::None => Box::new(reader),
+            Self::Gzip => Box::new(MultiGzDecoder::new(reader)),
+            Self::Bzip2 => Box::new(BzDecoder::new(reader)),
+            Self::Xz => Box::new(XzDecoder::new_multi_decoder(reader)),
+            Self::Zstd => Box::new(ZstdDecoder::new(reader)?),
+            Self::Lz4 => Box::new(Lz4Decoder::new(reader)),
+            Self::Lzma => Box::new(XzDecoder::new_stream(
+                reader,
+                XzStream::new_lzma_decoder(u64::MAX)?,
+            )),
+            Self::Lzw => Box::new(LzwDecoder::new(reader)),
+        })
+    }
+
+    /// File name suffixes (without the dot) of the compression format
+    pub fn suffixes(self) -> &'static [&'static str] {
+        match self {
+            Self::None => &[],
+            Self::Gzip => &["gz", "gzip"],
+            Self::Bzip2 => &["bz2"],
+            Self::Xz => &["xz"],
+            Self::Zstd => &["zst"],
+            Self::Lz4 => &["lz4"],
+            Self::Lzma => &["lzma"],
+            Self::Lzw => &["Z"],
+        }
+    }
+
+    /// Strip the compression suffix from a file name.
+    ///
+    /// `dump.sql.gz` becomes `dump.sql`, names without a known suffix are
+    /// returned as is.
+    pub fn strip_suffix(self, file_name: &str) -> String {
+        for suffix in self.suffixes() {
+            if let Some((stem, ext)) = file_name.rsplit_once('.') {
+                if !stem.is_empty() && ext.eq_ignore_ascii_case(suffix) {
+                    return stem.to_string();
+                }
+            }
+        }
+        file_name.to_string()
+    }
+}
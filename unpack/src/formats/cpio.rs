@@ -0,0 +1,147 @@
+//! cpio archives in the `newc` (and `crc`) and `odc` (portable ASCII) formats
+use std::{
+    fs::File,
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, ensure, Result};
+
+use crate::{
+    error::UnpackError,
+    formats::{kinds::Archive, path::Destination},
+};
+
+const NEWC_MAGIC: &[u8] = b"070701";
+const NEWC_CRC_MAGIC: &[u8] = b"070702";
+const ODC_MAGIC: &[u8] = b"070707";
+const TRAILER: &str = "TRAILER!!!";
+/// Maximum size of an entry name, `PATH_MAX` of linux
+const MAX_NAME_SIZE: u64 = 4096;
+
+/// File type bits of the entry mode
+const MODE_TYPE_MASK: u32 = 0o170_000;
+const MODE_DIR: u32 = 0o040_000;
+const MODE_FILE: u32 = 0o100_000;
+
+#[derive(Debug)]
+pub struct CpioArchive {
+    path: PathBuf,
+}
+
+impl CpioArchive {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+}
+
+impl Archive for CpioArchive {
+    ///  Get the the archive file path
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// unpack cpio file to destination directory
+    fn unpack_to(&mut self, destination: &mut Destination) -> Result<()> {
+        unpack_cpio(BufReader::new(File::open(&self.path)?), destination)
+    }
+}
+
+/// Entry header, common to all the supported cpio formats
+struct Header {
+    mode: u32,
+    name: String,
+    file_size: u64,
+    /// alignment of the header + name and of the file data
+    align: u64,
+}
+
+/// unpack a cpio stream to destination directory. Only directories and
+/// regular files are extracted, links and special files are skipped.
+pub fn unpack_cpio<R: Read>(mut reader: R, destination: &mut Destination) -> Result<()> {
+    loop {
+        let header = read_header(&mut reader)?;
+        if header.name == TRAILER {
+            return Ok(());
+        }
+
+        let mut data = (&mut reader).take(header.file_size);
+        let name = header.name.trim_start_matches("./");
+        match header.mode & MODE_TYPE_MASK {
+            MODE_DIR if !name.is_empty() && name != "." => destination.create_dir(name)?,
+            MODE_FILE => {
+                destination.write(name, &mut data)?;
+            }
+            MODE_DIR => {}
+            _ => {
+                log::debug!("skip cpio entry: {}", header.name);
+                destination.skip_entry()?;
+            }
+        }
+        io::copy(&mut data, &mut io::sink())?;
+        skip(&mut reader, padding(header.file_size, header.align))?;
+    }
+}
+
+fn read_header<R: Read>(reader: &mut R) -> Result<Header> {
+    let mut magic = [0_u8; 6];
+    reader.read_exact(&mut magic)?;
+    let (mode, name_size, file_size, align) = if magic == NEWC_MAGIC || magic == NEWC_CRC_MAGIC {
+        // ino, mode, uid, gid, nlink, mtime, filesize, devmajor, devminor,
+        // rdevmajor, rdevminor, namesize, check
+        let mut fields = [0_u8; 13 * 8];
+        reader.read_exact(&mut fields)?;
+        let field = |index: usize| parse_number(&fields[index * 8..(index + 1) * 8], 16);
+        (field(1)?, field(11)?, field(6)?, 4)
+    } else if magic == ODC_MAGIC {
+        // dev, ino, mode, uid, gid, nlink, rdev, mtime, namesize, filesize
+        let mut fields = [0_u8; 7 * 6 + 11 + 6 + 11];
+        reader.read_exact(&mut fields)?;
+        (
+            parse_number(&fields[12..18], 8)?,
+            parse_number(&fields[53..59], 8)?,
+            parse_number(&fields[59..70], 8)?,
+            1,
+        )
+    } else {
+        bail!(UnpackError::unsupported("unsupported cpio format"));
+    };
+
+    ensure!(
+        name_size <= MAX_NAME_SIZE,
+        UnpackError::corrupt("cpio entry name is too long")
+    );
+    let mut name = vec![0_u8; usize::try_from(name_size)?];
+    reader.read_exact(&mut name)?;
+    if align > 1 {
+        // newc header is 110 bytes long
+        skip(reader, padding(110 + name_size, align))?;
+    }
+    let name = String::from_utf8_lossy(name.split(|b| *b == 0).next().unwrap_or_default());
+
+    Ok(Header {
+        mode: u32::try_from(mode)?,
+        name: name.to_string(),
+        file_size,
+        align,
+    })
+}
+
+fn parse_number(field: &[u8], radix: u32) -> Result<u64> {
+    let field =
+        std::str::from_utf8(field).map_err(|_| UnpackError::corrupt("corrupt cpio header"))?;
+    u64::from_str_radix(field, radix)
+        .map_err(|_| UnpackError::corrupt("corrupt cpio header").into())
+}
+
+/// Number of padding bytes to the next alignment
+fn padding(size: u64, align: u64) -> u64 {
+    (align - size % align) % align
+}
+
+fn skip<R: Read>(reader: &mut R, size: u64) -> Result<()> {
+    io::copy(&mut reader.take(size), &mut io::sink())?;
+    Ok(())
+}
@@ -1,28 +1,137 @@
 //! Supported archive formats
-use std::path::Path;
+use std::{
+    fs::File,
+    io::{Cursor, Read},
+    path::Path,
+};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use lazy_static::lazy_static;
 use regex::Regex;
+use serde::Serialize;
 
-use crate::formats::zip::ZipArchive;
+use crate::{
+    data::{DetectionMode, PathPolicy},
+    error::UnpackError,
+    formats::{
+        ar::{unpack_ar, ArArchive},
+        compression::Compression,
+        cpio::{unpack_cpio, CpioArchive},
+        path::Destination,
+        rar,
+        rar::RarArchive,
+        rpm::{unpack_rpm, RpmArchive},
+        sevenz::{unpack_sevenz, SevenZArchive},
+        sfx,
+        sfx::SelfExtractingArchive,
+        stream::{unpack_stream, CompressedFile},
+        tar::{unpack_tar, TarArchive},
+        zip::{unpack_zip, ZipArchive},
+    },
+};
+
+/// Number of bytes read from the file head for content detection
+pub const MAGIC_HEADER_SIZE: u64 = 512;
 
 lazy_static! {
     static ref BY_PATTERN: Vec<(Regex, ArchiveKind)> = vec![
         (Regex::new(r"(?i)\.zip$").unwrap(), ArchiveKind::Zip),
-        (Regex::new(r"(?i)\.jar$").unwrap(), ArchiveKind::Zip)
+        (Regex::new(r"(?i)\.jar$").unwrap(), ArchiveKind::Zip),
+        // java archives (`.jmod` files have a 4 bytes header before the zip)
+        (
+            Regex::new(r"(?i)\.(war|ear|aar|sar|hpi|kar|jmod)$").unwrap(),
+            ArchiveKind::Zip
+        ),
+        (Regex::new(r"(?i)\.7z$").unwrap(), ArchiveKind::SevenZ),
+        (Regex::new(r"(?i)\.rar$").unwrap(), ArchiveKind::Rar),
+        (Regex::new(r"(?i)\.(deb|udeb|ar)$").unwrap(), ArchiveKind::Ar),
+        (Regex::new(r"(?i)\.cpio$").unwrap(), ArchiveKind::Cpio),
+        (Regex::new(r"(?i)\.rpm$").unwrap(), ArchiveKind::Rpm),
+        (Regex::new(r"(?i)\.tar$").unwrap(), ArchiveKind::Tar),
+        (
+            Regex::new(r"(?i)\.(tar\.gz|tgz)$").unwrap(),
+            ArchiveKind::TarGz
+        ),
+        (
+            Regex::new(r"(?i)\.(tar\.bz2|tbz2?)$").unwrap(),
+            ArchiveKind::TarBz2
+        ),
+        (
+            Regex::new(r"(?i)\.(tar\.xz|txz)$").unwrap(),
+            ArchiveKind::TarXz
+        ),
+        (
+            Regex::new(r"(?i)\.(tar\.zst|tzst)$").unwrap(),
+            ArchiveKind::TarZst
+        ),
+        (Regex::new(r"(?i)\.(gz|gzip)$").unwrap(), ArchiveKind::Gzip),
+        (Regex::new(r"(?i)\.bz2$").unwrap(), ArchiveKind::Bzip2),
+        (Regex::new(r"(?i)\.xz$").unwrap(), ArchiveKind::Xz),
+        (Regex::new(r"(?i)\.zst$").unwrap(), ArchiveKind::Zstd),
+        (Regex::new(r"(?i)\.lz4$").unwrap(), ArchiveKind::Lz4),
+        (Regex::new(r"(?i)\.lzma$").unwrap(), ArchiveKind::Lzma),
+        (Regex::new(r"\.Z$").unwrap(), ArchiveKind::Lzw)
+    ];
+    /// Signatures (offset, magic bytes) of the file header of each archive kind
+    static ref BY_MAGIC: Vec<(usize, &'static [u8], ArchiveKind)> = vec![
+        (0, b"PK\x03\x04", ArchiveKind::Zip),
+        (0, b"PK\x05\x06", ArchiveKind::Zip),
+        (0, b"PK\x07\x08", ArchiveKind::Zip),
+        (0, b"JM\x01\x00", ArchiveKind::Zip),
+        (0, b"7z\xbc\xaf\x27\x1c", ArchiveKind::SevenZ),
+        (0, b"Rar!\x1a\x07\x00", ArchiveKind::Rar),
+        (0, b"Rar!\x1a\x07\x01\x00", ArchiveKind::Rar),
+        (0, b"!<arch>\n", ArchiveKind::Ar),
+        (0, b"070701", ArchiveKind::Cpio),
+        (0, b"070702", ArchiveKind::Cpio),
+        (0, b"070707", ArchiveKind::Cpio),
+        (0, b"\xed\xab\xee\xdb", ArchiveKind::Rpm),
+        (257, b"ustar", ArchiveKind::Tar),
+        (0, b"\x1f\x8b", ArchiveKind::Gzip),
+        (0, b"BZh", ArchiveKind::Bzip2),
+        (0, b"\xfd7zXZ\x00", ArchiveKind::Xz),
+        (0, b"\x28\xb5\x2f\xfd", ArchiveKind::Zstd),
+        (0, b"\x04\x22\x4d\x18", ArchiveKind::Lz4),
+        (0, b"\x1f\x9d", ArchiveKind::Lzw),
     ];
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Serialize, Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ArchiveKind {
     Zip,
+    SevenZ,
+    Rar,
+    Ar,
+    Cpio,
+    Rpm,
+    /// zip, 7z or rar payload appended to an executable or a script, detected
+    /// by content (or by the extension of the executable, `.exe`, `.sh` and
+    /// `.bin`)
+    SelfExtracting,
+    Tar,
+    TarGz,
+    TarBz2,
+    TarXz,
+    TarZst,
+    Gzip,
+    Bzip2,
+    Xz,
+    Zstd,
+    Lz4,
+    Lzma,
+    Lzw,
 }
 
 pub trait Archive {
     fn path(&self) -> &Path;
 
-    fn unpack(&mut self, path: &Path) -> Result<()>;
+    /// unpack the archive entries to the destination
+    fn unpack_to(&mut self, destination: &mut Destination) -> Result<()>;
+
+    /// unpack the archive to the directory, failing on entries which escape it
+    fn unpack(&mut self, directory: &Path) -> Result<()> {
+        self.unpack_to(&mut Destination::new(directory, PathPolicy::default()))
+    }
 }
 
 impl ArchiveKind {
@@ -32,11 +141,117 @@ impl ArchiveKind {
         Self::determine_by_filename(path)
     }
 
+    /// Check if the given file is a supported archive type with the given
+    /// detection mode.
+    ///
+    /// With [`DetectionMode::Both`] the file header wins over the extension,
+    /// so a renamed archive is still detected and a file with an archive
+    /// extension but unrelated content is skipped. Kinds without a reliable
+    /// signature (e.g. `.lzma`) are trusted by their extension.
+    #[must_use]
+    pub fn detect(path: &Path, mode: DetectionMode) -> Option<Self> {
+        Self::detect_with(path, mode, || Self::determine_by_content(path))
+    }
+
+    /// Check if a file which its head is in memory is a supported archive
+    /// type, as [`ArchiveKind::detect`] without searching a self-extracting
+    /// payload
+    #[must_use]
+    pub fn detect_header(path: &Path, header: &[u8], mode: DetectionMode) -> Option<Self> {
+        Self::detect_with(path, mode, || Self::determine_by_magic(header))
+    }
+
+    fn detect_with(
+        path: &Path,
+        mode: DetectionMode,
+        by_content: impl FnOnce() -> Option<Self>,
+    ) -> Option<Self> {
+        match mode {
+            // executables and scripts have no archive extension, their payload
+            // is searched instead
+            DetectionMode::Extension => Self::determine_by_filename(path).or_else(|| {
+                sfx::is_stub_name(path)
+                    .then(by_content)
+                    .flatten()
+                    .filter(|kind| *kind == Self::SelfExtracting)
+            }),
+            DetectionMode::Content => by_content(),
+            DetectionMode::Both => {
+                let by_filename = Self::determine_by_filename(path);
+                match (by_filename, by_content()) {
+                    // the extension is more specific (`.tar.gz` is a tar in a gzip stream)
+                    (Some(by_filename), Some(by_content))
+                        if by_filename.container() == by_content =>
+                    {
+                        Some(by_filename)
+                    }
+                    (_, Some(by_content)) => Some(by_content),
+                    (Some(by_filename), None) if !by_filename.has_magic() => Some(by_filename),
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    /// determine by the file header if the path is an archive file
+    fn determine_by_content(path: &Path) -> Option<Self> {
+        let mut header = vec![];
+        if let Err(e) =
+            File::open(path).and_then(|f| f.take(MAGIC_HEADER_SIZE).read_to_end(&mut header))
+        {
+            log::debug!("could not read file header: {}. err: {}", path.display(), e);
+            return None;
+        }
+        Self::determine_by_magic(&header).or_else(|| {
+            if !sfx::is_stub(&header) {
+                return None;
+            }
+            match sfx::find_payload(path) {
+                Ok(payload) => payload.map(|_| Self::SelfExtracting),
+                Err(e) => {
+                    log::debug!("could not search payload: {}. err: {}", path.display(), e);
+                    None
+                }
+            }
+        })
+    }
+
+    /// determine by the magic bytes of a file header
+    fn determine_by_magic(header: &[u8]) -> Option<Self> {
+        BY_MAGIC
+            .iter()
+            .find(|(offset, magic, _)| header.get(*offset..offset + magic.len()) == Some(magic))
+            .map(|&(_, _, ty)| ty)
+            // next volumes of a rar set are read with the first volume
+            .filter(|ty| *ty != Self::Rar || rar::is_first_volume(header))
+    }
+
+    /// The outer format of the file, which is the kind its header is detected as
+    fn container(self) -> Self {
+        match self {
+            Self::TarGz => Self::Gzip,
+            Self::TarBz2 => Self::Bzip2,
+            Self::TarXz => Self::Xz,
+            Self::TarZst => Self::Zstd,
+            kind => kind,
+        }
+    }
+
+    /// Check if the archive kind can be detected by its file header
+    fn has_magic(self) -> bool {
+        let container = self.container();
+        BY_MAGIC.iter().any(|&(_, _, ty)| ty == container)
+    }
+
     /// determine by file name if the path is an archive file
     fn determine_by_filename(path: &Path) -> Option<Self> {
         if let Some(filename) = path.file_name().and_then(std::ffi::OsStr::to_str) {
             for &(ref regex, ty) in BY_PATTERN.iter() {
                 if regex.is_match(filename) {
+                    // next volumes of a rar set are read with the first volume
+                    if ty == Self::Rar && !rar::is_first_volume_name(filename) {
+                        return None;
+                    }
                     return Some(ty);
                 }
             }
@@ -50,6 +265,117 @@ impl ArchiveKind {
     pub fn new(self, path: &Path) -> Box<dyn Archive> {
         match self {
             Self::Zip => Box::new(ZipArchive::new(path)),
+            Self::SevenZ => Box::new(SevenZArchive::new(path)),
+            Self::Rar => Box::new(RarArchive::new(path)),
+            Self::Ar => Box::new(ArArchive::new(path)),
+            Self::Cpio => Box::new(CpioArchive::new(path)),
+            Self::Rpm => Box::new(RpmArchive::new(path)),
+            Self::SelfExtracting => Box::new(SelfExtractingArchive::new(path)),
+            Self::Tar => Box::new(TarArchive::new(path, Compression::None)),
+            Self::TarGz => Box::new(TarArchive::new(path, Compression::Gzip)),
+            Self::TarBz2 => Box::new(TarArchive::new(path, Compression::Bzip2)),
+            Self::TarXz => Box::new(TarArchive::new(path, Compression::Xz)),
+            Self::TarZst => Box::new(TarArchive::new(path, Compression::Zstd)),
+            Self::Gzip => Box::new(CompressedFile::new(path, Compression::Gzip)),
+            Self::Bzip2 => Box::new(CompressedFile::new(path, Compression::Bzip2)),
+            Self::Xz => Box::new(CompressedFile::new(path, Compression::Xz)),
+            Self::Zstd => Box::new(CompressedFile::new(path, Compression::Zstd)),
+            Self::Lz4 => Box::new(CompressedFile::new(path, Compression::Lz4)),
+            Self::Lzma => Box::new(CompressedFile::new(path, Compression::Lzma)),
+            Self::Lzw => Box::new(CompressedFile::new(path, Compression::Lzw)),
+        }
+    }
+
+    /// Check if the archive can be unpacked from memory, rar archives (and
+    /// their self-extracting form) are read from their files
+    #[must_use]
+    pub fn can_unpack_in_memory(self) -> bool {
+        !matches!(self, Self::Rar | Self::SelfExtracting)
+    }
+
+    /// unpack the archive content to the destination, `path` is the archive
+    /// file path the content was read from
+    pub fn unpack_bytes(
+        self,
+        path: &Path,
+        data: &[u8],
+        destination: &mut Destination,
+    ) -> Result<()> {
+        let reader = Cursor::new(data);
+        match self {
+            Self::Zip => unpack_zip(reader, destination),
+            Self::SevenZ => unpack_sevenz(reader, destination),
+            Self::Ar => unpack_ar(reader, destination),
+            Self::Cpio => unpack_cpio(reader, destination),
+            Self::Rpm => unpack_rpm(reader, destination),
+            Self::Tar => unpack_tar(reader, Compression::None, destination),
+            Self::TarGz => unpack_tar(reader, Compression::Gzip, destination),
+            Self::TarBz2 => unpack_tar(reader, Compression::Bzip2, destination),
+            Self::TarXz => unpack_tar(reader, Compression::Xz, destination),
+            Self::TarZst => unpack_tar(reader, Compression::Zstd, destination),
+            Self::Gzip => unpack_stream(reader, path, Compression::Gzip, destination),
+            Self::Bzip2 => unpack_stream(reader, path, Compression::Bzip2, destination),
+            Self::Xz => unpack_stream(reader, path, Compression::Xz, destination),
+            Self::Zstd => unpack_stream(reader, path, Compression::Zstd, destination),
+            Self::Lz4 => unpack_stream(reader, path, Compression::Lz4, destination),
+            Self::Lzma => unpack_stream(reader, path, Compression::Lzma, destination),
+            Self::Lzw => unpack_stream(reader, path, Compression::Lzw, destination),
+            Self::Rar | Self::SelfExtracting => {
+                bail!(UnpackError::unsupported(format!(
+                    "{:?} archives can only be unpacked from a file",
+                    self
+                )))
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod test_kinds {
+
+    use insta::assert_debug_snapshot;
+
+    use super::*;
+
+    #[test]
+    fn can_determine_multi_suffix_names() {
+        let kinds = [
+            "foo.tar",
+            "foo.tar.gz",
+            "foo.TGZ",
+            "foo.tar.bz2",
+            "foo.tbz",
+            "foo.tar.xz",
+            "foo.tar.zst",
+            "foo.gz.txt",
+            "tar.gz",
+            "dump.sql.gz",
+            "config.json.xz",
+            "foo.bz2",
+            "foo.zst",
+            "foo.lz4",
+            "foo.lzma",
+            "foo.Z",
+            "foo.z",
+            "foo.rar",
+            "foo.part1.rar",
+            "foo.part02.rar",
+            "foo.deb",
+            "foo.cpio",
+            "foo.rpm",
+            "foo.src.rpm",
+            "foo.war",
+            "foo.EAR",
+            "foo.aar",
+            "foo.sar",
+            "foo.hpi",
+            "foo.kar",
+            "foo.jmod",
+        ]
+        .iter()
+        .map(|name| (*name, ArchiveKind::for_path(Path::new(name))))
+        .collect::<Vec<_>>();
+
+        assert_debug_snapshot!(kinds);
+    }
+}
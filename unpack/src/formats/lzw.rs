@@ -0,0 +1,261 @@
+//! Decoder for the unix `compress` (.Z) LZW format
+use std::io::{self, Read};
+
+use crate::error::UnpackError;
+
+const MAGIC: [u8; 2] = [0x1f, 0x9d];
+const INIT_BITS: u32 = 9;
+const MAX_BITS: u32 = 16;
+const CLEAR: usize = 256;
+
+/// Reader that decodes a `.Z` stream.
+///
+/// The stream is decoded while it is read, one group of codes of the input
+/// and the string of one code of the output are buffered.
+pub struct LzwDecoder<R: Read> {
+    inner: R,
+    state: Option<State>,
+}
+
+impl<R: Read> LzwDecoder<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            inner: reader,
+            state: None,
+        }
+    }
+}
+
+impl<R: Read> Read for LzwDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let state = match &mut self.state {
+            Some(state) => state,
+            None => self.state.insert(State::new(&mut self.inner)?),
+        };
+        let mut read = 0;
+        while read < buf.len() {
+            match state.stack.pop() {
+                Some(byte) => {
+                    buf[read] = byte;
+                    read += 1;
+                }
+                // the bytes of the next code are only decoded when nothing was
+                // read yet, a read returns what is available
+                None if read == 0 => {
+                    if !state.decode_next(&mut self.inner)? {
+                        break;
+                    }
+                }
+                None => break,
+            }
+        }
+        Ok(read)
+    }
+}
+
+/// Decoding state of a stream after its header
+struct State {
+    block_mode: bool,
+    max_bits: u32,
+    max_max_code: usize,
+    prefix: Vec<u16>,
+    suffix: Vec<u8>,
+    /// the string of the last code, reversed
+    stack: Vec<u8>,
+    n_bits: u32,
+    max_code: usize,
+    free_ent: usize,
+    /// codes are written in groups of `n_bits` bytes, a code size change skips
+    /// to the end of the current group
+    group: [u8; MAX_BITS as usize],
+    group_len: usize,
+    /// bit position in the group
+    pos: usize,
+    old_code: Option<usize>,
+    fin_char: u8,
+}
+
+impl State {
+    fn new<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let mut header = [0_u8; 3];
+        reader
+            .read_exact(&mut header)
+            .map_err(|_| corrupt("not a compress (.Z) stream"))?;
+        if header[..2] != MAGIC {
+            return Err(corrupt("not a compress (.Z) stream"));
+        }
+        let flags = header[2];
+        let max_bits = u32::from(flags & 0x1f);
+        if !(INIT_BITS..=MAX_BITS).contains(&max_bits) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                UnpackError::unsupported(format!("unsupported compress max bits: {}", max_bits)),
+            ));
+        }
+        let block_mode = flags & 0x80 != 0;
+        let max_max_code = 1_usize << max_bits;
+
+        Ok(Self {
+            block_mode,
+            max_bits,
+            max_max_code,
+            prefix: vec![0_u16; max_max_code],
+            suffix: (0..max_max_code).map(|c| c as u8).collect(),
+            stack: vec![],
+            n_bits: INIT_BITS,
+            max_code: (1 << INIT_BITS) - 1,
+            free_ent: if block_mode { CLEAR + 1 } else { CLEAR },
+            group: [0; MAX_BITS as usize],
+            group_len: 0,
+            pos: 0,
+            old_code: None,
+            fin_char: 0,
+        })
+    }
+
+    /// Decode the string of the next code to the stack, `false` at the end of
+    /// the stream
+    fn decode_next<R: Read>(&mut self, reader: &mut R) -> io::Result<bool> {
+        loop {
+            if self.free_ent > self.max_code {
+                self.next_group();
+                self.n_bits += 1;
+                self.max_code = if self.n_bits == self.max_bits {
+                    self.max_max_code
+                } else {
+                    (1 << self.n_bits) - 1
+                };
+            }
+
+            let Some(mut code) = self.read_code(reader)? else {
+                return Ok(false);
+            };
+
+            let Some(old) = self.old_code else {
+                if code >= CLEAR {
+                    return Err(corrupt("corrupt compress stream: invalid first code"));
+                }
+                self.fin_char = code as u8;
+                self.stack.push(self.fin_char);
+                self.old_code = Some(code);
+                return Ok(true);
+            };
+
+            if code == CLEAR && self.block_mode {
+                self.free_ent = CLEAR;
+                self.next_group();
+                self.n_bits = INIT_BITS;
+                self.max_code = (1 << self.n_bits) - 1;
+                continue;
+            }
+
+            let in_code = code;
+            if code >= self.free_ent {
+                if code > self.free_ent {
+                    return Err(corrupt("corrupt compress stream: code out of range"));
+                }
+                self.stack.push(self.fin_char);
+                code = old;
+            }
+            while code >= CLEAR {
+                self.stack.push(self.suffix[code]);
+                code = usize::from(self.prefix[code]);
+            }
+            self.fin_char = self.suffix[code];
+            self.stack.push(self.fin_char);
+
+            if self.free_ent < self.max_max_code {
+                self.prefix[self.free_ent] = old as u16;
+                self.suffix[self.free_ent] = self.fin_char;
+                self.free_ent += 1;
+            }
+            self.old_code = Some(in_code);
+            return Ok(true);
+        }
+    }
+
+    /// Skip to the end of the current code group
+    fn next_group(&mut self) {
+        self.group_len = 0;
+        self.pos = 0;
+    }
+
+    /// Read the next little-endian code of `n_bits`, `None` at the end of the
+    /// stream
+    fn read_code<R: Read>(&mut self, reader: &mut R) -> io::Result<Option<usize>> {
+        let n_bits = self.n_bits as usize;
+        if self.pos + n_bits > self.group_len * 8 {
+            // a group shorter than `n_bits` bytes is the last one
+            if self.group_len != 0 && self.group_len < n_bits {
+                return Ok(None);
+            }
+            self.group_len = read_full(reader, &mut self.group[..n_bits])?;
+            self.pos = 0;
+            if n_bits > self.group_len * 8 {
+                return Ok(None);
+            }
+        }
+
+        let byte = self.pos / 8;
+        let mut value = 0_usize;
+        for (i, b) in self.group[..self.group_len]
+            .iter()
+            .skip(byte)
+            .take(3)
+            .enumerate()
+        {
+            value |= usize::from(*b) << (i * 8);
+        }
+        let code = (value >> (self.pos % 8)) & ((1 << n_bits) - 1);
+        self.pos += n_bits;
+        Ok(Some(code))
+    }
+}
+
+/// Read until the buffer is full or the end of the reader, returns the number
+/// of bytes read
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(read) => len += read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(len)
+}
+
+fn corrupt(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, UnpackError::corrupt(message))
+}
+
+#[cfg(test)]
+mod test_lzw {
+
+    use std::{fs, io::BufReader, path::Path};
+
+    use super::*;
+
+    #[test]
+    fn can_decode_in_small_reads() {
+        // 10 bits codes, the table is cleared several times
+        let path = Path::new("tests").join("mocks").join("lzw");
+        let expected = fs::read(path.join("words.txt")).unwrap();
+        let mut decoder = LzwDecoder::new(BufReader::new(
+            fs::File::open(path.join("words.txt.Z")).unwrap(),
+        ));
+
+        let mut decoded = vec![];
+        let mut buf = [0_u8; 7];
+        loop {
+            let read = decoder.read(&mut buf).unwrap();
+            if read == 0 {
+                break;
+            }
+            decoded.extend_from_slice(&buf[..read]);
+        }
+        assert_eq!(decoded, expected);
+    }
+}
@@ -1,2 +1,15 @@
+pub mod ar;
+pub mod compression;
+pub mod cpio;
 pub mod kinds;
+pub mod lzw;
+pub mod path;
+pub mod rar;
+pub mod rpm;
+pub mod sevenz;
+pub mod sfx;
+pub mod stream;
+pub mod tar;
+#[cfg(feature = "rar")]
+mod unrar;
 pub mod zip;
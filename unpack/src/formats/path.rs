@@ -0,0 +1,680 @@
+//! Entry path handling shared by the archive formats.
+//!
+//! Every format writes its entries through a [`Destination`], which keeps
+//! them inside the destination directory according to the [`PathPolicy`] and
+//! enforces the [`Limits`] while the data is written. Nested archives up to
+//! the in-memory limit are kept in memory instead of being written, and with
+//! a [`Visitor`] no entry is written at all: the nested archives which cannot
+//! be kept in memory are spilled to a temporary file.
+use std::{
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    data::{DetectionMode, LimitExceeded, Limits, PathPolicy, RewrittenEntry},
+    error::UnpackError,
+    formats::kinds::{ArchiveKind, MAGIC_HEADER_SIZE},
+    manifest::{ContentHasher, ManifestEntry, ManifestHashes},
+    report::{Event, Report},
+    virtual_path::VirtualPath,
+    visitor::{EntryMetadata, Visitor},
+};
+
+/// Entry name split to its safe components
+#[derive(Debug, PartialEq, Eq)]
+struct SafeName {
+    path: PathBuf,
+    /// the name has `..` components, is absolute or has a drive or UNC prefix
+    is_unsafe: bool,
+}
+
+/// Split an entry name to its components, dropping every component that
+/// could escape the destination directory. Both `/` and `\` are separators.
+fn safe_name(name: &str) -> SafeName {
+    let mut is_unsafe = false;
+    let mut rest = name;
+
+    if rest.starts_with("//") || rest.starts_with("\\\\") {
+        // UNC prefix (`\\server\share\`, `\\?\C:\`)
+        is_unsafe = true;
+        rest = rest
+            .splitn(4, ['/', '\\'])
+            .nth(3)
+            .and_then(|r| r.split_once(['/', '\\']).map(|(_, r)| r))
+            .unwrap_or_default();
+    } else if rest.len() >= 2
+        && rest.as_bytes()[1] == b':'
+        && rest.as_bytes()[0].is_ascii_alphabetic()
+    {
+        // drive letter (`C:\`, `C:`)
+        is_unsafe = true;
+        rest = &rest[2..];
+    }
+    if rest.starts_with(['/', '\\']) {
+        is_unsafe = true;
+    }
+
+    let mut path = PathBuf::new();
+    for component in rest.split(['/', '\\']) {
+        match component {
+            "" | "." => {}
+            ".." => is_unsafe = true,
+            component => path.push(component),
+        }
+    }
+    SafeName { path, is_unsafe }
+}
+
+/// Archive entry kept in memory, to be unpacked without writing it
+#[derive(Debug)]
+pub struct InMemoryArchive {
+    /// path the entry would have been written to
+    pub path: PathBuf,
+    pub virtual_path: VirtualPath,
+    pub kind: ArchiveKind,
+    pub data: Vec<u8>,
+}
+
+/// Nested archive of a visited archive written to a temporary file, it is
+/// larger than the in-memory limit or can only be unpacked from a file
+#[derive(Debug)]
+pub struct SpilledArchive {
+    /// path the entry would have been written to
+    pub path: PathBuf,
+    pub virtual_path: VirtualPath,
+    pub kind: ArchiveKind,
+    /// the temporary file
+    pub file: PathBuf,
+}
+
+/// Destination directory of an archive, the virtual path of the archive when
+/// its entries are visited
+pub struct Destination<'a> {
+    directory: PathBuf,
+    policy: PathPolicy,
+    rewritten: Vec<RewrittenEntry>,
+    limits: Limits,
+    /// size of the archive file, for the compression ratio
+    archive_size: u64,
+    /// bytes written by all the archives of the run
+    total_bytes: Arc<AtomicU64>,
+    archive_bytes: u64,
+    entries: u64,
+    /// file entries written, visited or kept in memory
+    extracted: u64,
+    /// entries skipped by the path policy or by the archive format
+    skipped: u64,
+    /// maximum size of an archive entry kept in memory
+    in_memory_limit: Option<u64>,
+    detection: DetectionMode,
+    in_memory: Vec<InMemoryArchive>,
+    /// folder of the nested archives spilled to a file, with a visitor
+    spill_folder: Option<PathBuf>,
+    spilled: Vec<SpilledArchive>,
+    visitor: Option<&'a dyn Visitor>,
+    report: Option<&'a dyn Report>,
+    /// hashes of the written files, for the manifest
+    manifest: Option<ManifestHashes>,
+    manifest_entries: Vec<ManifestEntry>,
+    /// virtual path of the archive, the entries are under it
+    virtual_path: VirtualPath,
+    /// unpack level of the archive, for the visited entries
+    depth: u32,
+}
+
+impl<'a> Destination<'a> {
+    pub fn new(directory: &Path, policy: PathPolicy) -> Self {
+        Self {
+            directory: directory.to_path_buf(),
+            policy,
+            rewritten: vec![],
+            limits: Limits::default(),
+            archive_size: 0,
+            total_bytes: Arc::default(),
+            archive_bytes: 0,
+            entries: 0,
+            extracted: 0,
+            skipped: 0,
+            in_memory_limit: None,
+            detection: DetectionMode::default(),
+            in_memory: vec![],
+            spill_folder: None,
+            spilled: vec![],
+            visitor: None,
+            report: None,
+            manifest: None,
+            manifest_entries: vec![],
+            virtual_path: VirtualPath::default(),
+            depth: 1,
+        }
+    }
+
+    /// Enforce the limits, the total bytes are shared by the archives of the
+    /// run
+    #[must_use]
+    pub fn with_limits(
+        self,
+        limits: Limits,
+        archive_size: u64,
+        total_bytes: Arc<AtomicU64>,
+    ) -> Self {
+        Self {
+            limits,
+            archive_size,
+            total_bytes,
+            ..self
+        }
+    }
+
+    /// Keep the archive entries up to `limit` bytes in memory instead of
+    /// writing them, the entries are detected with the given detection mode
+    #[must_use]
+    pub fn with_in_memory(self, limit: u64, detection: DetectionMode) -> Self {
+        Self {
+            in_memory_limit: Some(limit),
+            detection,
+            ..self
+        }
+    }
+
+    /// Give the file entries to the visitor instead of writing them, the
+    /// archive is at the given unpack level
+    #[must_use]
+    pub fn with_visitor(self, visitor: &'a dyn Visitor, depth: u32) -> Self {
+        Self {
+            visitor: Some(visitor),
+            depth,
+            ..self
+        }
+    }
+
+    /// Write the visited nested archives which cannot be kept in memory to
+    /// the folder, to unpack them from there
+    #[must_use]
+    pub fn with_spill(self, folder: &Path) -> Self {
+        Self {
+            spill_folder: Some(folder.to_path_buf()),
+            ..self
+        }
+    }
+
+    /// Virtual path of the archive, for the kept in memory and visited entries
+    #[must_use]
+    pub fn with_virtual_path(self, virtual_path: VirtualPath) -> Self {
+        Self {
+            virtual_path,
+            ..self
+        }
+    }
+
+    /// Report the written file entries
+    #[must_use]
+    pub fn with_report(self, report: Option<&'a dyn Report>) -> Self {
+        Self { report, ..self }
+    }
+
+    /// Hash the written files for the manifest
+    #[must_use]
+    pub fn with_manifest(self, manifest: Option<ManifestHashes>) -> Self {
+        Self { manifest, ..self }
+    }
+
+    /// Check if the file entries are given to a visitor
+    pub fn is_visiting(&self) -> bool {
+        self.visitor.is_some()
+    }
+
+    /// Archive entries which were kept in memory
+    pub fn take_in_memory(&mut self) -> Vec<InMemoryArchive> {
+        std::mem::take(&mut self.in_memory)
+    }
+
+    /// Nested archives which were spilled to a file
+    pub fn take_spilled(&mut self) -> Vec<SpilledArchive> {
+        std::mem::take(&mut self.spilled)
+    }
+
+    /// The destination directory
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Uncompressed bytes of the archive entries
+    pub fn archive_bytes(&self) -> u64 {
+        self.archive_bytes
+    }
+
+    /// Number of the file entries written, visited or kept in memory
+    pub fn extracted(&self) -> u64 {
+        self.extracted
+    }
+
+    /// Number of the entries skipped by the path policy or by the archive
+    /// format
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    /// Count an entry which the archive format does not extract (links,
+    /// special files, unsupported entries), as the written entries toward the
+    /// entries limit
+    ///
+    /// # Errors
+    /// When the entries limit is exceeded
+    pub fn skip_entry(&mut self) -> Result<()> {
+        self.count_entry()?;
+        self.skipped += 1;
+        Ok(())
+    }
+
+    /// Count an entry of the archive against the entries limit
+    fn count_entry(&mut self) -> Result<()> {
+        self.entries += 1;
+        match self.limits.max_entries {
+            Some(limit) if self.entries > limit => Err(LimitExceeded::Entries(limit).into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Hasher of a written file entry, `None` without a manifest
+    fn content_hasher(&self) -> Option<ContentHasher> {
+        self.manifest.map(ContentHasher::new)
+    }
+
+    /// Manifest entries of the written files
+    pub fn take_manifest(&mut self) -> Vec<ManifestEntry> {
+        std::mem::take(&mut self.manifest_entries)
+    }
+
+    /// Entries which their path was rewritten or which were skipped
+    pub fn into_rewritten(self) -> Vec<RewrittenEntry> {
+        self.rewritten
+    }
+
+    /// Resolve the entry name to a path inside the destination directory.
+    ///
+    /// Returns `None` when the entry is skipped.
+    ///
+    /// # Errors
+    /// When the entry escapes the destination directory with
+    /// [`PathPolicy::Reject`]
+    pub fn entry_path(&mut self, name: &str) -> Result<Option<PathBuf>> {
+        self.count_entry()?;
+
+        let SafeName { path, is_unsafe } = safe_name(name);
+        if !is_unsafe {
+            return Ok((!path.as_os_str().is_empty()).then(|| self.directory.join(path)));
+        }
+
+        match self.policy {
+            PathPolicy::Reject => bail!(UnpackError::UnsafePath(name.to_string())),
+            PathPolicy::SkipEntry => {
+                log::debug!("skip entry escaping the destination: {}", name);
+                self.rewritten.push(RewrittenEntry {
+                    entry: name.to_string(),
+                    path: None,
+                });
+                Ok(None)
+            }
+            PathPolicy::Sanitize => {
+                log::debug!("rewrite entry {} to {}", name, path.display());
+                let rewritten = (!path.as_os_str().is_empty()).then_some(path);
+                self.rewritten.push(RewrittenEntry {
+                    entry: name.to_string(),
+                    path: rewritten.clone(),
+                });
+                Ok(rewritten.map(|path| self.directory.join(path)))
+            }
+        }
+    }
+
+    /// Create the directory entry
+    pub fn create_dir(&mut self, name: &str) -> Result<()> {
+        if let Some(path) = self.entry_path(name)? {
+            if !self.is_visiting() {
+                fs::create_dir_all(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The data of a file entry was written
+    fn written(&mut self, path: &Path, bytes: u64, hasher: Option<ContentHasher>) {
+        let virtual_path = self.entry_virtual_path(path);
+        if let Some(report) = self.report {
+            report.event(&Event::EntryWritten {
+                virtual_path: virtual_path.clone(),
+                path,
+                bytes,
+            });
+        }
+        if let Some(hasher) = hasher {
+            self.manifest_entries
+                .push(hasher.finish(virtual_path, path, bytes));
+        }
+    }
+
+    /// Write the file entry data, returns the path of the written file. The
+    /// data of a skipped entry is consumed, `None` is returned for skipped
+    /// entries, archives kept in memory and visited entries. A file which
+    /// could not be written whole is removed.
+    pub fn write<R: Read + ?Sized>(
+        &mut self,
+        name: &str,
+        reader: &mut R,
+    ) -> Result<Option<PathBuf>> {
+        let Some(path) = self.entry_path(name)? else {
+            self.skipped += 1;
+            // the data is decompressed all the same, it counts for the limits
+            self.copy(&[], reader, &mut io::sink(), None)?;
+            return Ok(None);
+        };
+
+        // bytes read before the entry is written, to detect a nested archive
+        let mut head = vec![];
+        if let Some(limit) = self.in_memory_limit {
+            (&mut *reader)
+                .take(MAGIC_HEADER_SIZE)
+                .read_to_end(&mut head)?;
+            let kind = ArchiveKind::detect_header(&path, &head, self.detection);
+            let in_memory = kind.filter(|kind| kind.can_unpack_in_memory());
+            if in_memory.is_some() {
+                (&mut *reader)
+                    .take(limit.saturating_add(1).saturating_sub(head.len() as u64))
+                    .read_to_end(&mut head)?;
+            }
+
+            if let Some(kind) = in_memory.filter(|_| head.len() as u64 <= limit) {
+                self.account(head.len() as u64, head.len() as u64)?;
+                log::debug!("keep archive entry in memory: {}", path.display());
+                self.in_memory.push(InMemoryArchive {
+                    virtual_path: self.entry_virtual_path(&path),
+                    path,
+                    kind,
+                    data: head,
+                });
+                self.extracted += 1;
+                return Ok(None);
+            }
+            if let Some(kind) = kind.filter(|_| self.spill_folder.is_some()) {
+                self.spill(kind, path, &head, reader)?;
+                return Ok(None);
+            }
+        }
+
+        if let Some(visitor) = self.visitor {
+            self.visit(visitor, &path, &mut io::Cursor::new(head).chain(reader))?;
+            self.extracted += 1;
+            return Ok(None);
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut file = File::create(&path)?;
+        let mut hasher = self.content_hasher();
+        let entry_bytes = match self.copy(&head, reader, &mut file, hasher.as_mut()) {
+            Ok(entry_bytes) => entry_bytes,
+            Err(e) => {
+                drop(file);
+                if let Err(remove) = fs::remove_file(&path) {
+                    log::debug!(
+                        "could not remove partial file: {}. err: {}",
+                        path.display(),
+                        remove
+                    );
+                }
+                return Err(e);
+            }
+        };
+        self.extracted += 1;
+        self.written(&path, entry_bytes, hasher);
+        Ok(Some(path))
+    }
+
+    /// Write the nested archive of a visited archive to a file of the spill
+    /// folder, named after the entry in a folder of its own
+    fn spill<R: Read + ?Sized>(
+        &mut self,
+        kind: ArchiveKind,
+        path: PathBuf,
+        head: &[u8],
+        reader: &mut R,
+    ) -> Result<()> {
+        let virtual_path = self.entry_virtual_path(&path);
+        let folder = self
+            .spill_folder
+            .as_ref()
+            .context("no spill folder")?
+            .join(format!(
+                "{:x}",
+                Sha256::digest(virtual_path.to_string().as_bytes())
+            ));
+        fs::create_dir_all(&folder)?;
+        let file = folder.join(path.file_name().unwrap_or_else(|| "archive".as_ref()));
+
+        let mut writer = File::create(&file)?;
+        if let Err(e) = self.copy(head, reader, &mut writer, None) {
+            drop(writer);
+            if let Err(remove) = fs::remove_file(&file) {
+                log::debug!(
+                    "could not remove partial file: {}. err: {}",
+                    file.display(),
+                    remove
+                );
+            }
+            return Err(e);
+        }
+        log::debug!(
+            "spill archive entry: {} to: {}",
+            path.display(),
+            file.display()
+        );
+        self.spilled.push(SpilledArchive {
+            path,
+            virtual_path,
+            kind,
+            file,
+        });
+        self.extracted += 1;
+        Ok(())
+    }
+
+    /// Copy the head, then the rest of the entry data, to the writer while
+    /// it is accounted against the limits, returns the size of the entry
+    fn copy<R: Read + ?Sized, W: Write>(
+        &mut self,
+        head: &[u8],
+        reader: &mut R,
+        writer: &mut W,
+        mut hasher: Option<&mut ContentHasher>,
+    ) -> Result<u64> {
+        let mut entry_bytes = head.len() as u64;
+        if !head.is_empty() {
+            self.account(entry_bytes, entry_bytes)?;
+            writer.write_all(head)?;
+            if let Some(hasher) = &mut hasher {
+                hasher.update(head);
+            }
+        }
+        let mut buf = vec![0_u8; 64 * 1024];
+        loop {
+            let read = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            entry_bytes += read as u64;
+            self.account(read as u64, entry_bytes)?;
+            writer.write_all(&buf[..read])?;
+            if let Some(hasher) = &mut hasher {
+                hasher.update(&buf[..read]);
+            }
+        }
+        Ok(entry_bytes)
+    }
+
+    /// Virtual path of an entry path of the destination directory
+    fn entry_virtual_path(&self, path: &Path) -> VirtualPath {
+        self.virtual_path
+            .join(path.strip_prefix(&self.directory).unwrap_or(path))
+    }
+
+    /// Give the file entry to the visitor
+    fn visit(&mut self, visitor: &dyn Visitor, path: &Path, reader: &mut dyn Read) -> Result<()> {
+        let virtual_path = self.entry_virtual_path(path);
+        let metadata = EntryMetadata {
+            archive: self.virtual_path.clone(),
+            depth: self.depth,
+        };
+        let mut reader = EntryReader {
+            destination: self,
+            reader,
+            entry_bytes: 0,
+            exceeded: None,
+        };
+        let result = visitor.on_entry(&virtual_path, &metadata, &mut reader);
+        // the data left by the visitor counts for the limits
+        let drained = match (&result, &reader.exceeded) {
+            (Ok(()), None) => io::copy(&mut reader, &mut io::sink()).map(|_| ()),
+            _ => Ok(()),
+        };
+        match reader.exceeded {
+            Some(exceeded) => Err(exceeded),
+            None => {
+                result.map_err(|e| UnpackError::Visitor(e.into()))?;
+                Ok(drained?)
+            }
+        }
+    }
+
+    /// Account bytes about to be written against the limits, `entry_bytes` is
+    /// the size of the entry so far (the bytes included).
+    ///
+    /// # Errors
+    /// [`LimitExceeded`] when a limit is exceeded
+    pub fn account(&mut self, bytes: u64, entry_bytes: u64) -> Result<()> {
+        self.archive_bytes += bytes;
+        let total_bytes = self.total_bytes.fetch_add(bytes, Ordering::Relaxed) + bytes;
+
+        let limits = self.limits;
+        let exceeded = if limits
+            .max_entry_bytes
+            .is_some_and(|limit| entry_bytes > limit)
+        {
+            limits.max_entry_bytes.map(LimitExceeded::EntryBytes)
+        } else if limits
+            .max_archive_bytes
+            .is_some_and(|limit| self.archive_bytes > limit)
+        {
+            limits.max_archive_bytes.map(LimitExceeded::ArchiveBytes)
+        } else if limits
+            .max_total_bytes
+            .is_some_and(|limit| total_bytes > limit)
+        {
+            limits.max_total_bytes.map(LimitExceeded::TotalBytes)
+        } else if limits.max_ratio.is_some_and(|limit| {
+            self.archive_bytes > limit.saturating_mul(self.archive_size.max(1))
+        }) {
+            limits.max_ratio.map(LimitExceeded::Ratio)
+        } else {
+            None
+        };
+        match exceeded {
+            Some(exceeded) => Err(exceeded.into()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Reader of a visited entry, accounting its bytes against the limits
+struct EntryReader<'d, 'a, R> {
+    destination: &'d mut Destination<'a>,
+    reader: R,
+    entry_bytes: u64,
+    /// the limit which stopped the reader
+    exceeded: Option<anyhow::Error>,
+}
+
+impl<'d, 'a, R: Read> Read for EntryReader<'d, 'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.reader.read(buf)?;
+        self.entry_bytes += read as u64;
+        if let Err(e) = self.destination.account(read as u64, self.entry_bytes) {
+            let err = io::Error::other(e.to_string());
+            self.exceeded = Some(e);
+            return Err(err);
+        }
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod test_path {
+
+    use insta::assert_debug_snapshot;
+
+    use super::*;
+
+    #[test]
+    fn can_split_safe_names() {
+        let names = [
+            "foo/bar.txt",
+            "./foo/./bar.txt",
+            "foo\\bar.txt",
+            "../bar.txt",
+            "foo/../../bar.txt",
+            "/etc/passwd",
+            "C:\\Windows\\bar.txt",
+            "c:bar.txt",
+            "\\\\server\\share\\bar.txt",
+            "//server/share/bar.txt",
+            "\\\\?\\C:\\bar.txt",
+            "..",
+        ]
+        .iter()
+        .map(|name| {
+            let SafeName { path, is_unsafe } = safe_name(name);
+            // components joined with `/` on every platform
+            let path = path
+                .iter()
+                .map(|c| c.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            (*name, path, is_unsafe)
+        })
+        .collect::<Vec<_>>();
+
+        assert_debug_snapshot!(names);
+    }
+
+    #[test]
+    fn can_limit_skipped_entries() {
+        let limits = Limits {
+            max_entries: Some(2),
+            ..Limits::default()
+        };
+        let mut destination = Destination::new(Path::new("dest"), PathPolicy::default())
+            .with_limits(limits, 0, Arc::default());
+
+        destination.skip_entry().unwrap();
+        destination.skip_entry().unwrap();
+        let err = destination.skip_entry().unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<LimitExceeded>(),
+            Some(&LimitExceeded::Entries(2))
+        );
+        assert_eq!(destination.skipped(), 2);
+    }
+}
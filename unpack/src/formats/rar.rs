@@ -0,0 +1,174 @@
+//! RAR (v4 and v5) archives, decompressed by the unrar library when the `rar`
+//! feature is enabled (see the `unrar` module). Without it they are reported as
+//! unsupported.
+//!
+//! Multi-volume sets (`.partN.rar` or `.rar`, `.r00`, ...) are read from their
+//! first volume, and unrar finds the archive of a self-extracting executable
+//! by itself.
+//!
+//! Java resource adapter archives share the `.rar` extension, they are zip
+//! files and are unpacked as such with or without the feature.
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+use lazy_static::lazy_static;
+use regex::Regex;
+
+#[cfg(feature = "rar")]
+use crate::formats::unrar::unpack_rar;
+use crate::{
+    error::UnpackError,
+    formats::{kinds::Archive, path::Destination, zip::unpack_zip},
+};
+
+const RAR4_SIGNATURE: &[u8] = b"Rar!\x1a\x07\x00";
+const RAR5_SIGNATURE: &[u8] = b"Rar!\x1a\x07\x01\x00";
+
+/// RAR 4 block type of the main header
+const RAR4_MAIN_HEAD: u8 = 0x73;
+/// RAR 5 header type of the main header
+const RAR5_MAIN_HEAD: u64 = 1;
+
+lazy_static! {
+    static ref PART_VOLUME: Regex = Regex::new(r"(?i)^(.*\.part)(\d+)(\.rar)$").unwrap();
+}
+
+/// Check if a file name is the first volume of a multi-volume set (or not a
+/// multi-volume set at all)
+pub fn is_first_volume_name(file_name: &str) -> bool {
+    PART_VOLUME
+        .captures(file_name)
+        .and_then(|c| c[2].parse::<u32>().ok())
+        .is_none_or(|part| part == 1)
+}
+
+/// Check if a rar file header belongs to the first volume (or a single volume
+/// archive)
+pub fn is_first_volume(header: &[u8]) -> bool {
+    if header.starts_with(RAR5_SIGNATURE) {
+        let mut rdr = &header[RAR5_SIGNATURE.len()..];
+        // crc, size, type and flags of the main header
+        let main = (|| -> Result<Option<u64>> {
+            let mut crc = [0_u8; 4];
+            rdr.read_exact(&mut crc)?;
+            read_vint(&mut rdr)?;
+            if read_vint(&mut rdr)? != RAR5_MAIN_HEAD {
+                return Ok(None);
+            }
+            let flags = read_vint(&mut rdr)?;
+            if flags & 0x0001 != 0 {
+                read_vint(&mut rdr)?;
+            }
+            if flags & 0x0002 != 0 {
+                read_vint(&mut rdr)?;
+            }
+            Ok(Some(read_vint(&mut rdr)?))
+        })();
+        // a volume number is stored for all the volumes except the first one
+        return !matches!(main, Ok(Some(archive_flags)) if archive_flags & 0x0002 != 0);
+    }
+    if header.starts_with(RAR4_SIGNATURE) {
+        let main = &header[RAR4_SIGNATURE.len()..];
+        if main.len() >= 5 && main[2] == RAR4_MAIN_HEAD {
+            let flags = u16::from_le_bytes([main[3], main[4]]);
+            // a volume without the "first volume" flag
+            return flags & 0x0001 == 0 || flags & 0x0100 != 0;
+        }
+    }
+    true
+}
+
+/// Check the CRC of the main header which follows the signature, the
+/// signature alone is too short to tell a payload from stub bytes
+pub fn is_main_header(data: &[u8]) -> bool {
+    if let Some(header) = data.strip_prefix(RAR5_SIGNATURE) {
+        // crc32 of the header size and the header
+        let Some((crc, mut rdr)) = header.split_first_chunk::<4>() else {
+            return false;
+        };
+        let Ok(size) = read_vint(&mut rdr) else {
+            return false;
+        };
+        let end = usize::try_from(size)
+            .ok()
+            .and_then(|size| (header.len() - rdr.len()).checked_add(size));
+        return end
+            .and_then(|end| header.get(4..end))
+            .is_some_and(|checked| {
+                u32::from_le_bytes(*crc) == crc32fast::hash(checked)
+                    && read_vint(&mut rdr).is_ok_and(|ty| ty == RAR5_MAIN_HEAD)
+            });
+    }
+    if let Some(header) = data.strip_prefix(RAR4_SIGNATURE) {
+        // low bits of the crc32 of the header after its crc field
+        return header.get(..7).is_some_and(|fixed| {
+            let size = usize::from(u16::from_le_bytes([fixed[5], fixed[6]]));
+            fixed[2] == RAR4_MAIN_HEAD
+                && header.get(2..size).is_some_and(|checked| {
+                    u32::from(u16::from_le_bytes([fixed[0], fixed[1]]))
+                        == crc32fast::hash(checked) & 0xffff
+                })
+        });
+    }
+    false
+}
+
+/// Read a RAR 5 variable length integer
+fn read_vint<R: Read>(reader: &mut R) -> Result<u64> {
+    let mut value = 0_u64;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0_u8; 1];
+        reader.read_exact(&mut byte)?;
+        value |= u64::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    bail!(UnpackError::corrupt("corrupt rar variable length integer"))
+}
+
+#[derive(Debug)]
+pub struct RarArchive {
+    path: PathBuf,
+}
+
+impl RarArchive {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+}
+
+impl Archive for RarArchive {
+    ///  Get the the archive file path
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// unpack a rar file (and its next volumes) to destination directory
+    fn unpack_to(&mut self, destination: &mut Destination) -> Result<()> {
+        let mut magic = [0_u8; 4];
+        let is_zip =
+            File::open(&self.path)?.read_exact(&mut magic).is_ok() && &magic == b"PK\x03\x04";
+        if is_zip {
+            return unpack_zip(BufReader::new(File::open(&self.path)?), destination);
+        }
+
+        unpack_rar(&self.path, destination)
+    }
+}
+
+/// Built without the unrar library, only the zip content of a resource adapter
+/// archive can be unpacked
+#[cfg(not(feature = "rar"))]
+fn unpack_rar(path: &Path, _destination: &mut Destination) -> Result<()> {
+    bail!(UnpackError::unsupported(format!(
+        "rar archives need the `rar` feature: {}",
+        path.display()
+    )))
+}
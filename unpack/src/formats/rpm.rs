@@ -0,0 +1,174 @@
+//! RPM packages: a lead, a signature header, a header and a compressed cpio
+//! payload
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, ensure, Result};
+
+use crate::{
+    error::UnpackError,
+    formats::{compression::Compression, cpio::unpack_cpio, kinds::Archive, path::Destination},
+};
+
+const LEAD_SIZE: usize = 96;
+const LEAD_MAGIC: &[u8] = b"\xed\xab\xee\xdb";
+const HEADER_MAGIC: &[u8] = b"\x8e\xad\xe8\x01";
+/// Header tag of the payload compressor name
+const TAG_PAYLOAD_COMPRESSOR: u32 = 1125;
+/// Header tag type of a string value
+const TYPE_STRING: u32 = 6;
+/// Limits of the header index entries and data store, as rpm checks them
+const MAX_INDEX_COUNT: usize = 0x00ff_ffff;
+const MAX_STORE_SIZE: usize = 256 * 1024 * 1024;
+
+#[derive(Debug)]
+pub struct RpmArchive {
+    path: PathBuf,
+}
+
+impl RpmArchive {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+}
+
+impl Archive for RpmArchive {
+    ///  Get the the archive file path
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// unpack the cpio payload of the rpm file to destination directory
+    fn unpack_to(&mut self, destination: &mut Destination) -> Result<()> {
+        unpack_rpm(BufReader::new(File::open(&self.path)?), destination)
+    }
+}
+
+/// unpack the cpio payload of a rpm stream to destination directory
+pub fn unpack_rpm<R: Read>(mut reader: R, destination: &mut Destination) -> Result<()> {
+    let mut lead = [0_u8; LEAD_SIZE];
+    reader.read_exact(&mut lead)?;
+    ensure!(
+        lead.starts_with(LEAD_MAGIC),
+        UnpackError::corrupt("invalid rpm lead")
+    );
+
+    // the signature header is padded to 8 bytes
+    let signature = Header::read(&mut reader)?;
+    let mut padding = vec![0_u8; (8 - signature.size() % 8) % 8];
+    reader.read_exact(&mut padding)?;
+
+    let header = Header::read(&mut reader)?;
+    let compression = match header.string(TAG_PAYLOAD_COMPRESSOR)?.as_deref() {
+        None | Some("gzip") => Compression::Gzip,
+        Some("bzip2") => Compression::Bzip2,
+        Some("xz") => Compression::Xz,
+        Some("lzma") => Compression::Lzma,
+        Some("zstd") => Compression::Zstd,
+        Some(compressor) => bail!(UnpackError::unsupported(format!(
+            "unsupported rpm payload compressor: {}",
+            compressor
+        ))),
+    };
+
+    unpack_cpio(compression.decoder(reader)?, destination)
+}
+
+/// Header structure: index entries and their data store
+struct Header {
+    index: Vec<u8>,
+    store: Vec<u8>,
+}
+
+impl Header {
+    fn read<R: Read>(reader: &mut R) -> Result<Self> {
+        let mut preamble = [0_u8; 16];
+        reader.read_exact(&mut preamble)?;
+        ensure!(
+            preamble.starts_with(HEADER_MAGIC),
+            UnpackError::corrupt("invalid rpm header")
+        );
+        let index_count = usize::try_from(be_u32(&preamble, 8)?)?;
+        let store_size = usize::try_from(be_u32(&preamble, 12)?)?;
+        ensure!(
+            index_count <= MAX_INDEX_COUNT && store_size <= MAX_STORE_SIZE,
+            UnpackError::corrupt("rpm header is too large")
+        );
+
+        let index = read_bytes(reader, index_count * 16)?;
+        let store = read_bytes(reader, store_size)?;
+        Ok(Self { index, store })
+    }
+
+    /// Size of the header structure, preamble included
+    fn size(&self) -> usize {
+        16 + self.index.len() + self.store.len()
+    }
+
+    /// Get a string value of the header by its tag
+    fn string(&self, tag: u32) -> Result<Option<String>> {
+        for entry in self.index.chunks_exact(16) {
+            if be_u32(entry, 0)? != tag {
+                continue;
+            }
+            ensure!(
+                be_u32(entry, 4)? == TYPE_STRING,
+                UnpackError::corrupt("corrupt rpm header")
+            );
+            let offset = usize::try_from(be_u32(entry, 8)?)?;
+            let value = self
+                .store
+                .get(offset..)
+                .ok_or_else(|| UnpackError::corrupt("corrupt rpm header"))?;
+            let value = value.split(|b| *b == 0).next().unwrap_or_default();
+            return Ok(Some(String::from_utf8_lossy(value).to_string()));
+        }
+        Ok(None)
+    }
+}
+
+/// Read the given number of bytes, the buffer grows with the bytes actually
+/// read and not with the size of the untrusted header field
+fn read_bytes<R: Read>(reader: &mut R, size: usize) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    reader.take(u64::try_from(size)?).read_to_end(&mut buf)?;
+    ensure!(
+        buf.len() == size,
+        UnpackError::corrupt("truncated rpm header")
+    );
+    Ok(buf)
+}
+
+fn be_u32(buf: &[u8], offset: usize) -> Result<u32> {
+    let bytes = buf
+        .get(offset..offset + 4)
+        .ok_or_else(|| UnpackError::corrupt("corrupt rpm header"))?;
+    Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+#[cfg(test)]
+mod test_rpm {
+
+    use super::*;
+
+    #[test]
+    fn can_fail_on_truncated_header() {
+        let mut preamble = HEADER_MAGIC.to_vec();
+        preamble.extend_from_slice(&[0; 4]);
+        preamble.extend_from_slice(&1_u32.to_be_bytes());
+        preamble.extend_from_slice(&(64 * 1024 * 1024_u32).to_be_bytes());
+        preamble.extend_from_slice(&[0; 16 + 8]);
+
+        let err = Header::read(&mut preamble.as_slice()).err().unwrap();
+        assert_eq!(err.to_string(), "truncated rpm header");
+
+        preamble[12..16].copy_from_slice(&u32::MAX.to_be_bytes());
+        let err = Header::read(&mut preamble.as_slice()).err().unwrap();
+        assert_eq!(err.to_string(), "rpm header is too large");
+    }
+}
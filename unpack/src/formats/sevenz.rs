@@ -0,0 +1,54 @@
+use std::{
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use sevenz_rust::{Password, SevenZReader};
+
+use crate::formats::{kinds::Archive, path::Destination};
+
+#[derive(Debug)]
+pub struct SevenZArchive {
+    path: PathBuf,
+}
+
+impl SevenZArchive {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+}
+
+impl Archive for SevenZArchive {
+    ///  Get the the archive file path
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// unpack 7z file (LZMA/LZMA2, solid or not) to destination directory
+    fn unpack_to(&mut self, destination: &mut Destination) -> Result<()> {
+        unpack_sevenz(BufReader::new(File::open(&self.path)?), destination)
+    }
+}
+
+/// unpack a 7z stream to destination directory
+pub fn unpack_sevenz<R: Read + Seek>(mut reader: R, destination: &mut Destination) -> Result<()> {
+    let len = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(0))?;
+    let mut rdr = SevenZReader::new(reader, len, Password::empty())?;
+
+    // entries are read from the solid blocks in order, stop on the first error
+    let mut result = Ok(());
+    rdr.for_each_entries(|entry, data| {
+        result = if entry.is_directory() {
+            destination.create_dir(entry.name())
+        } else {
+            destination.write(entry.name(), data).map(|_| ())
+        };
+        Ok(result.is_ok())
+    })?;
+    result
+}
@@ -0,0 +1,307 @@
+//! Self-extracting archives: a zip, 7z or rar payload appended to an
+//! executable or a script (installers, executable jars, ...)
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+
+use crate::{
+    error::UnpackError,
+    formats::{
+        kinds::Archive,
+        path::Destination,
+        rar::{self, RarArchive},
+        sevenz::unpack_sevenz,
+        zip::unpack_zip,
+    },
+};
+
+/// File headers of the executables and scripts that carry a payload
+const STUB_MAGIC: &[&[u8]] = &[
+    b"MZ",
+    b"\x7fELF",
+    b"\xfe\xed\xfa\xce",
+    b"\xfe\xed\xfa\xcf",
+    b"\xce\xfa\xed\xfe",
+    b"\xcf\xfa\xed\xfe",
+    b"#!",
+];
+
+/// Extensions of the executables and scripts searched for a payload when the
+/// archives are detected by their extension
+const STUB_EXTENSIONS: &[&str] = &["exe", "sh", "bin"];
+
+const EOCD_SIGNATURE: &[u8] = b"PK\x05\x06";
+const EOCD_SIZE: u64 = 22;
+const CENTRAL_DIRECTORY_SIGNATURE: &[u8] = b"PK\x01\x02";
+const SEVENZ_SIGNATURE: &[u8] = b"7z\xbc\xaf\x27\x1c";
+
+/// Maximum size of the stub searched for a 7z or rar signature, as unrar does
+const MAX_STUB_SIZE: u64 = 0x0020_0000;
+
+/// Format of the archive appended to the stub
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PayloadKind {
+    Zip,
+    SevenZ,
+    Rar,
+}
+
+/// Location of the archive appended to the stub
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Payload {
+    pub kind: PayloadKind,
+    pub offset: u64,
+    pub len: u64,
+}
+
+/// Check if the file header is an executable or a script that may carry a
+/// payload
+pub fn is_stub(header: &[u8]) -> bool {
+    STUB_MAGIC.iter().any(|magic| header.starts_with(magic))
+}
+
+/// Check if the file name is the one of an executable or a script that may
+/// carry a payload
+pub fn is_stub_name(path: &Path) -> bool {
+    path.extension()
+        .and_then(std::ffi::OsStr::to_str)
+        .is_some_and(|ext| STUB_EXTENSIONS.iter().any(|e| ext.eq_ignore_ascii_case(e)))
+}
+
+/// Locate the archive appended to the given file.
+///
+/// A zip payload is found by its end of central directory record, a 7z or rar
+/// payload by its signature followed by a header with a valid CRC in the first
+/// 2MB of the file.
+pub fn find_payload(path: &Path) -> Result<Option<Payload>> {
+    let mut file = File::open(path)?;
+    let file_len = file.metadata()?.len();
+    if let Some(payload) = find_zip(&mut file, file_len)? {
+        return Ok(Some(payload));
+    }
+    find_signature(&mut file, file_len)
+}
+
+fn find_zip(file: &mut File, file_len: u64) -> Result<Option<Payload>> {
+    // the record is at the end of the file, followed by a comment of up to 64KB
+    let tail_start = file_len.saturating_sub(EOCD_SIZE + u64::from(u16::MAX));
+    file.seek(SeekFrom::Start(tail_start))?;
+    let mut tail = vec![];
+    file.read_to_end(&mut tail)?;
+
+    for index in (0..tail.len().saturating_sub(3)).rev() {
+        if !tail[index..].starts_with(EOCD_SIGNATURE) {
+            continue;
+        }
+        let Some(record) = tail.get(index..index + 22) else {
+            continue;
+        };
+        let cd_size = u64::from(le_u32(&record[12..16]));
+        let cd_offset = u64::from(le_u32(&record[16..20]));
+        let comment_len = u64::from(u16::from_le_bytes([record[20], record[21]]));
+
+        let eocd_pos = tail_start + index as u64;
+        let end = eocd_pos + EOCD_SIZE + comment_len;
+        if cd_size == 0 || end > file_len || cd_offset + cd_size > eocd_pos {
+            continue;
+        }
+
+        // offsets of the central directory are relative to the start of the
+        // zip, or to the start of the file when the stub was accounted for
+        let cd_pos = eocd_pos - cd_size;
+        let mut signature = [0_u8; 4];
+        file.seek(SeekFrom::Start(cd_pos))?;
+        file.read_exact(&mut signature)?;
+        if signature == CENTRAL_DIRECTORY_SIGNATURE {
+            let offset = cd_pos - cd_offset;
+            return Ok(Some(Payload {
+                kind: PayloadKind::Zip,
+                offset,
+                len: end - offset,
+            }));
+        }
+    }
+    Ok(None)
+}
+
+fn find_signature(file: &mut File, file_len: u64) -> Result<Option<Payload>> {
+    file.seek(SeekFrom::Start(0))?;
+    let mut stub = vec![];
+    file.take(MAX_STUB_SIZE).read_to_end(&mut stub)?;
+
+    for offset in 1..stub.len() {
+        let data = &stub[offset..];
+        let kind = if data.starts_with(SEVENZ_SIGNATURE) && is_sevenz_start_header(data) {
+            PayloadKind::SevenZ
+        } else if rar::is_main_header(data) {
+            PayloadKind::Rar
+        } else {
+            continue;
+        };
+        let offset = offset as u64;
+        return Ok(Some(Payload {
+            kind,
+            offset,
+            len: file_len - offset,
+        }));
+    }
+    Ok(None)
+}
+
+/// Check the CRC of the 7z start header, the signature alone is too short
+/// to tell a payload from stub bytes
+fn is_sevenz_start_header(data: &[u8]) -> bool {
+    data.get(8..32)
+        .is_some_and(|header| le_u32(&header[..4]) == crc32fast::hash(&header[4..]))
+}
+
+fn le_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// A reader of a part of a file, seen as a whole file
+struct Window<R> {
+    inner: R,
+    start: u64,
+    len: u64,
+    pos: u64,
+}
+
+impl<R: Seek> Window<R> {
+    fn new(mut inner: R, start: u64, len: u64) -> io::Result<Self> {
+        inner.seek(SeekFrom::Start(start))?;
+        Ok(Self {
+            inner,
+            start,
+            len,
+            pos: 0,
+        })
+    }
+}
+
+impl<R: Read> Read for Window<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = usize::try_from(self.len.saturating_sub(self.pos)).unwrap_or(usize::MAX);
+        let max = buf.len().min(remaining);
+        let read = self.inner.read(&mut buf[..max])?;
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: Seek> Seek for Window<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(delta) => self.len.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek position"))?
+        // the window ends with the payload, there is nothing to read after it
+        .min(self.len);
+        self.inner.seek(SeekFrom::Start(self.start + pos))?;
+        self.pos = pos;
+        Ok(pos)
+    }
+}
+
+#[derive(Debug)]
+pub struct SelfExtractingArchive {
+    path: PathBuf,
+}
+
+impl SelfExtractingArchive {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+}
+
+impl Archive for SelfExtractingArchive {
+    ///  Get the the archive file path
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// unpack the archive payload of the file to destination directory
+    fn unpack_to(&mut self, destination: &mut Destination) -> Result<()> {
+        let payload = find_payload(&self.path)?
+            .ok_or_else(|| UnpackError::unsupported("could not find an archive payload"))?;
+        log::debug!(
+            "found {:?} payload at offset {}: {}",
+            payload.kind,
+            payload.offset,
+            self.path.display()
+        );
+
+        let window = || {
+            Window::new(
+                BufReader::new(File::open(&self.path)?),
+                payload.offset,
+                payload.len,
+            )
+        };
+        match payload.kind {
+            PayloadKind::Zip => unpack_zip(window()?, destination),
+            PayloadKind::SevenZ => unpack_sevenz(window()?, destination),
+            PayloadKind::Rar => RarArchive::new(&self.path).unpack_to(destination),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_sfx {
+
+    use insta::assert_debug_snapshot;
+
+    use super::*;
+
+    #[test]
+    fn can_find_payload() {
+        let payloads = [
+            "setup.exe",
+            "setup-7z.exe",
+            "setup-rar.exe",
+            "installer.sh",
+            "plain.exe",
+        ]
+        .iter()
+        .map(|name| {
+            let path = Path::new("tests").join("mocks").join("sfx").join(name);
+            (*name, find_payload(&path).unwrap())
+        })
+        .collect::<Vec<_>>();
+
+        assert_debug_snapshot!(payloads);
+    }
+
+    #[test]
+    fn can_skip_rar_signature_without_header() {
+        let folder = std::env::temp_dir().join(format!("deep-unpack-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&folder).unwrap();
+        let path = folder.join("setup.exe");
+        let mut data = b"MZ\0\0Rar!\x1a\x07\x01\x00not a header".to_vec();
+        let offset = data.len() as u64;
+        data.extend(std::fs::read("tests/mocks/rar-compressed/archive.rar").unwrap());
+        std::fs::write(&path, &data).unwrap();
+
+        let payload = find_payload(&path).unwrap().unwrap();
+        assert_eq!(payload.kind, PayloadKind::Rar);
+        assert_eq!(payload.offset, offset);
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn can_read_window_after_seeking_past_its_end() {
+        let mut window = Window::new(io::Cursor::new(b"stubpayload"), 4, 7).unwrap();
+        assert_eq!(window.seek(SeekFrom::Current(10)).unwrap(), 7);
+        let mut rest = vec![];
+        window.read_to_end(&mut rest).unwrap();
+        assert!(rest.is_empty());
+    }
+}
@@ -0,0 +1,77 @@
+use std::{
+    fs::File,
+    io::{BufReader, Cursor, Read},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+use crate::formats::{
+    compression::Compression,
+    kinds::{Archive, ArchiveKind, MAGIC_HEADER_SIZE},
+    path::Destination,
+    tar::is_tar_header,
+};
+
+/// A single compressed file (e.g. `dump.sql.gz`), decompressed to one output
+/// file named after the original without the compression suffix
+#[derive(Debug)]
+pub struct CompressedFile {
+    path: PathBuf,
+    compression: Compression,
+}
+
+impl CompressedFile {
+    pub fn new(path: &Path, compression: Compression) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            compression,
+        }
+    }
+}
+
+impl Archive for CompressedFile {
+    ///  Get the the archive file path
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// decompress the file stream to destination directory
+    fn unpack_to(&mut self, destination: &mut Destination) -> Result<()> {
+        unpack_stream(
+            BufReader::new(File::open(&self.path)?),
+            &self.path,
+            self.compression,
+            destination,
+        )
+    }
+}
+
+/// Decompress a stream to destination directory, the output file is named
+/// after the `path` of the stream without the compression suffix
+pub fn unpack_stream<R: Read>(
+    reader: R,
+    path: &Path,
+    compression: Compression,
+    destination: &mut Destination,
+) -> Result<()> {
+    let file_name = path
+        .file_name()
+        .and_then(std::ffi::OsStr::to_str)
+        .context("could not get file name")?;
+    let mut output_name = compression.strip_suffix(file_name);
+    let mut reader = compression.decoder(reader)?;
+
+    // a tar stream that lost its `.tar` suffix (e.g. `bundle.gz`) gets it
+    // back, so the next unpack level detects it as an archive
+    let mut head = vec![];
+    (&mut reader)
+        .take(MAGIC_HEADER_SIZE)
+        .read_to_end(&mut head)?;
+    if ArchiveKind::for_path(Path::new(&output_name)).is_none() && is_tar_header(&head) {
+        output_name.push_str(".tar");
+    }
+
+    destination.write(&output_name, &mut Cursor::new(head).chain(reader))?;
+    Ok(())
+}
@@ -0,0 +1,71 @@
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use tar::Archive as TarArchiveReader;
+
+use crate::formats::{compression::Compression, kinds::Archive, path::Destination};
+
+/// Check if the given bytes start with a POSIX (ustar) tar header
+pub fn is_tar_header(header: &[u8]) -> bool {
+    header.get(257..262) == Some(b"ustar".as_slice())
+}
+
+#[derive(Debug)]
+pub struct TarArchive {
+    path: PathBuf,
+    compression: Compression,
+}
+
+impl TarArchive {
+    pub fn new(path: &Path, compression: Compression) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            compression,
+        }
+    }
+}
+
+impl Archive for TarArchive {
+    ///  Get the the archive file path
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// unpack tar file (optionally compressed) to destination directory. Only
+    /// directories and regular files are extracted, links and special files
+    /// are skipped.
+    fn unpack_to(&mut self, destination: &mut Destination) -> Result<()> {
+        unpack_tar(
+            BufReader::new(File::open(&self.path)?),
+            self.compression,
+            destination,
+        )
+    }
+}
+
+/// unpack a tar stream (optionally compressed) to destination directory
+pub fn unpack_tar<R: Read>(
+    reader: R,
+    compression: Compression,
+    destination: &mut Destination,
+) -> Result<()> {
+    let mut rdr = TarArchiveReader::new(compression.decoder(reader)?);
+    for entry in rdr.entries()? {
+        let mut entry = entry?;
+        let name = String::from_utf8_lossy(&entry.path_bytes()).to_string();
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_dir() {
+            destination.create_dir(&name)?;
+        } else if entry_type.is_file() {
+            destination.write(&name, &mut entry)?;
+        } else {
+            log::debug!("skip tar entry: {}", name);
+            destination.skip_entry()?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,359 @@
+//! Decompression of RAR archives by the unrar library, built with the `rar`
+//! feature.
+//!
+//! A worker thread decompresses the entries and sends their data to the
+//! destination while it is produced, so the limits are enforced as the data
+//! is written and stop the worker. Encrypted entries are skipped and reported
+//! as an error once the other entries are unpacked.
+use std::{
+    cell::Cell,
+    io::{self, Read},
+    os::raw::c_int,
+    path::Path,
+    ptr::{self, NonNull},
+    sync::mpsc::{self, Receiver, SyncSender},
+    thread,
+};
+
+use anyhow::{anyhow, bail, Result};
+use unrar_sys::{
+    HeaderDataEx, OpenArchiveDataEx, ERAR_BAD_ARCHIVE, ERAR_BAD_DATA, ERAR_BAD_PASSWORD,
+    ERAR_END_ARCHIVE, ERAR_EOPEN, ERAR_EREAD, ERAR_MISSING_PASSWORD, ERAR_SUCCESS,
+    ERAR_UNKNOWN_FORMAT, LPARAM, RAR_OM_EXTRACT, RAR_SKIP, RAR_TEST, RAR_VOL_ASK, RHDF_DIRECTORY,
+    RHDF_ENCRYPTED, UCM_CHANGEVOLUME, UCM_CHANGEVOLUMEW, UCM_NEEDPASSWORD, UCM_NEEDPASSWORDW,
+    UCM_PROCESSDATA, UINT, WCHAR,
+};
+
+use crate::{error::UnpackError, formats::path::Destination};
+
+/// Decompressed blocks on their way to the destination, the worker waits for
+/// the destination when they are all pending
+const PENDING_BLOCKS: usize = 4;
+
+/// Sent by the worker for every entry of the archive, in order
+enum Message {
+    /// a file entry, its data follows up to [`Message::EndFile`]
+    File(String),
+    Dir(String),
+    /// a link, its target is not extracted
+    Link(String),
+    /// an encrypted entry, skipped
+    Encrypted(String),
+    Data(Vec<u8>),
+    EndFile,
+}
+
+/// Open archive of the unrar library, closed on drop
+struct Handle(NonNull<unrar_sys::Handle>);
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        // SAFETY: the handle was opened by `RAROpenArchiveEx` and is closed once
+        unsafe { unrar_sys::RARCloseArchive(self.0.as_ptr()) };
+    }
+}
+
+/// Callback of the unrar library, the user data is the sender of the worker
+extern "C" fn callback(message: UINT, user_data: LPARAM, p1: LPARAM, p2: LPARAM) -> c_int {
+    // SAFETY: the sender outlives the handle the callback is registered with
+    let sender = unsafe { &*(user_data as *const SyncSender<Message>) };
+    match message {
+        UCM_PROCESSDATA => {
+            let len = usize::try_from(p2).unwrap_or_default();
+            // SAFETY: unrar passes a decompressed block and its size
+            let data = unsafe { std::slice::from_raw_parts(p1 as *const u8, len) };
+            // the destination stopped reading, stop decompressing
+            if sender.send(Message::Data(data.to_vec())).is_ok() {
+                1
+            } else {
+                -1
+            }
+        }
+        // no password is given and a missing volume is not waited for
+        UCM_NEEDPASSWORD | UCM_NEEDPASSWORDW => -1,
+        UCM_CHANGEVOLUME | UCM_CHANGEVOLUMEW if p2 == RAR_VOL_ASK => -1,
+        _ => 1,
+    }
+}
+
+/// Decompress the archive and send its entries, stops when the receiver is
+/// gone
+fn decompress(path: &Path, sender: SyncSender<Message>) -> Result<()> {
+    let archive_name = ArchiveName::new(path)?;
+    let mut data = OpenArchiveDataEx::new(ptr::null(), RAR_OM_EXTRACT);
+    archive_name.set(&mut data);
+    data.callback = Some(callback);
+    data.user_data = &sender as *const SyncSender<Message> as LPARAM;
+
+    // SAFETY: the archive name and the sender outlive the handle
+    let handle = NonNull::new(unsafe { unrar_sys::RAROpenArchiveEx(&data) }.cast_mut())
+        .map(Handle)
+        .ok_or_else(|| anyhow!("could not open rar archive {}", path.display()))?;
+    let code = c_int::try_from(data.open_result).unwrap_or(c_int::MAX);
+    if code != ERAR_SUCCESS {
+        return Err(unrar_error(code));
+    }
+
+    let mut header = Box::<HeaderDataEx>::default();
+    loop {
+        // SAFETY: the header is a valid header data structure, unrar fills it
+        let header_data = ptr::from_mut(&mut *header);
+        match unsafe { unrar_sys::RARReadHeaderEx(handle.0.as_ptr(), header_data) } {
+            ERAR_SUCCESS => {}
+            ERAR_END_ARCHIVE => return Ok(()),
+            code => return Err(unrar_error(code)),
+        }
+        let name = wide_to_string(&header.filename_w);
+        let message = if header.flags & RHDF_ENCRYPTED != 0 {
+            Message::Encrypted(name)
+        } else if header.flags & RHDF_DIRECTORY != 0 {
+            Message::Dir(name)
+        } else if header.redir_type != 0 {
+            Message::Link(name)
+        } else {
+            Message::File(name)
+        };
+        let is_file = matches!(message, Message::File(_));
+        if sender.send(message).is_err() {
+            return Ok(());
+        }
+
+        let operation = if is_file { RAR_TEST } else { RAR_SKIP };
+        // SAFETY: the data of the file is sent by the callback, no path is given
+        let code = unsafe {
+            unrar_sys::RARProcessFileW(handle.0.as_ptr(), operation, ptr::null(), ptr::null())
+        };
+        if code != ERAR_SUCCESS {
+            return Err(unrar_error(code));
+        }
+        if is_file && sender.send(Message::EndFile).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+fn unrar_error(code: c_int) -> anyhow::Error {
+    match code {
+        ERAR_MISSING_PASSWORD | ERAR_BAD_PASSWORD => {
+            UnpackError::encrypted("encrypted rar headers are not supported").into()
+        }
+        ERAR_BAD_DATA => UnpackError::corrupt("rar entry checksum mismatch").into(),
+        ERAR_BAD_ARCHIVE => UnpackError::corrupt("corrupt rar archive").into(),
+        ERAR_UNKNOWN_FORMAT => UnpackError::unsupported("unknown rar archive format").into(),
+        ERAR_EOPEN => {
+            UnpackError::corrupt("could not open the rar archive or its next volume").into()
+        }
+        ERAR_EREAD => UnpackError::corrupt("could not read the rar archive").into(),
+        code => anyhow!("unrar failed with error code {}", code),
+    }
+}
+
+/// Archive path in the form the unrar library expects, the bytes of the path
+/// on unix and UTF-16 on windows
+struct ArchiveName {
+    #[cfg(unix)]
+    name: std::ffi::CString,
+    #[cfg(windows)]
+    name: Vec<u16>,
+}
+
+impl ArchiveName {
+    #[cfg(unix)]
+    fn new(path: &Path) -> Result<Self> {
+        use std::os::unix::ffi::OsStrExt;
+        Ok(Self {
+            name: std::ffi::CString::new(path.as_os_str().as_bytes())?,
+        })
+    }
+
+    #[cfg(windows)]
+    fn new(path: &Path) -> Result<Self> {
+        use std::os::windows::ffi::OsStrExt;
+        Ok(Self {
+            name: path
+                .as_os_str()
+                .encode_wide()
+                .chain(std::iter::once(0))
+                .collect(),
+        })
+    }
+
+    #[cfg(unix)]
+    fn set(&self, data: &mut OpenArchiveDataEx) {
+        data.archive_name = self.name.as_ptr();
+        data.archive_name_w = ptr::null();
+    }
+
+    #[cfg(windows)]
+    fn set(&self, data: &mut OpenArchiveDataEx) {
+        data.archive_name = ptr::null();
+        data.archive_name_w = self.name.as_ptr();
+    }
+}
+
+/// Decode a nul terminated wide string of the unrar library
+#[cfg(not(windows))]
+fn wide_to_string(wide: &[WCHAR]) -> String {
+    wide.iter()
+        .take_while(|c| **c != 0)
+        .map(|c| {
+            u32::try_from(*c)
+                .ok()
+                .and_then(char::from_u32)
+                .unwrap_or(char::REPLACEMENT_CHARACTER)
+        })
+        .collect()
+}
+
+/// Decode a nul terminated wide string of the unrar library
+#[cfg(windows)]
+fn wide_to_string(wide: &[WCHAR]) -> String {
+    let len = wide.iter().position(|c| *c == 0).unwrap_or(wide.len());
+    String::from_utf16_lossy(&wide[..len])
+}
+
+/// Data of the current file entry, received from the worker
+struct EntryData<'r> {
+    receiver: &'r Receiver<Message>,
+    block: Vec<u8>,
+    pos: usize,
+    done: bool,
+    /// set when the worker stopped in the middle of the entry
+    truncated: &'r Cell<bool>,
+}
+
+impl<'r> Read for EntryData<'r> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.block.len() {
+            if self.done {
+                return Ok(0);
+            }
+            match self.receiver.recv() {
+                Ok(Message::Data(block)) => {
+                    self.block = block;
+                    self.pos = 0;
+                }
+                Ok(Message::EndFile) => self.done = true,
+                Ok(_) | Err(_) => {
+                    self.truncated.set(true);
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "rar entry data ends early",
+                    ));
+                }
+            }
+        }
+        let len = buf.len().min(self.block.len() - self.pos);
+        buf[..len].copy_from_slice(&self.block[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+/// Write the entries sent by the worker to the destination, returns the
+/// names of the encrypted entries
+fn receive(
+    receiver: Receiver<Message>,
+    destination: &mut Destination,
+    truncated: &Cell<bool>,
+) -> Result<Vec<String>> {
+    let mut encrypted = vec![];
+    while let Ok(message) = receiver.recv() {
+        match message {
+            Message::File(name) => {
+                let mut data = EntryData {
+                    receiver: &receiver,
+                    block: vec![],
+                    pos: 0,
+                    done: false,
+                    truncated,
+                };
+                destination.write(&name, &mut data)?;
+            }
+            Message::Dir(name) => destination.create_dir(&name)?,
+            Message::Link(name) => {
+                log::debug!("skip rar link entry: {}", name);
+                destination.skip_entry()?;
+            }
+            Message::Encrypted(name) => {
+                destination.skip_entry()?;
+                encrypted.push(name);
+            }
+            // the rest of an entry which was not read
+            Message::Data(_) | Message::EndFile => {}
+        }
+    }
+    Ok(encrypted)
+}
+
+/// Unpack a rar archive (and its next volumes) to the destination
+pub fn unpack_rar(path: &Path, destination: &mut Destination) -> Result<()> {
+    let (sender, receiver) = mpsc::sync_channel(PENDING_BLOCKS);
+    let truncated = Cell::new(false);
+    let (result, worker_result) = thread::scope(|scope| {
+        let worker = scope.spawn(move || decompress(path, sender));
+        // the receiver is dropped when the destination fails, which stops
+        // the worker
+        let result = receive(receiver, destination, &truncated);
+        let worker_result = worker
+            .join()
+            .unwrap_or_else(|_| Err(anyhow!("rar decompression panicked")));
+        (result, worker_result)
+    });
+
+    let encrypted = match result {
+        // the worker failed in the middle of an entry, its error tells why
+        Err(e) if truncated.get() => return Err(worker_result.err().unwrap_or(e)),
+        result => {
+            let encrypted = result?;
+            worker_result?;
+            encrypted
+        }
+    };
+    if !encrypted.is_empty() {
+        bail!(UnpackError::encrypted(format!(
+            "encrypted rar entries are not supported: {}",
+            encrypted.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_unrar {
+
+    use std::fs;
+
+    use super::*;
+    use crate::data::PathPolicy;
+
+    #[test]
+    fn can_fail_on_missing_volume() {
+        let folder = std::env::temp_dir().join(format!("deep-unpack-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&folder).unwrap();
+        let first = folder.join("archive.part1.rar");
+        fs::copy(
+            Path::new("tests")
+                .join("mocks")
+                .join("rar-volumes")
+                .join("archive.part1.rar"),
+            &first,
+        )
+        .unwrap();
+
+        let err = unpack_rar(
+            &first,
+            &mut Destination::new(&folder.join("dest"), PathPolicy::default()),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            UnpackError::from(err),
+            UnpackError::Corrupt(source) if source.to_string().contains("next volume")
+        ));
+        // the entries before the missing volume are written, the entry split
+        // across the volumes is removed
+        assert!(folder.join("dest").join("fernhollow.txt").exists());
+        assert!(!folder.join("dest").join("marrowdale.txt").exists());
+        fs::remove_dir_all(folder).unwrap();
+    }
+}
@@ -1,13 +1,13 @@
 use std::{
     fs::File,
-    io::BufReader,
+    io::{BufReader, Read, Seek},
     path::{Path, PathBuf},
 };
 
 use anyhow::Result;
 use zip::read::ZipArchive as ZipArchiveReader;
 
-use crate::formats::kinds::Archive;
+use crate::formats::{kinds::Archive, path::Destination};
 
 #[derive(Debug)]
 pub struct ZipArchive {
@@ -29,9 +29,22 @@ impl Archive for ZipArchive {
     }
 
     /// unpack zip file to destination directory
-    fn unpack(&mut self, directory: &Path) -> Result<()> {
-        let mut rdr = ZipArchiveReader::new(BufReader::new(File::open(&self.path)?))?;
-        rdr.extract(directory)?;
-        Ok(())
+    fn unpack_to(&mut self, destination: &mut Destination) -> Result<()> {
+        unpack_zip(BufReader::new(File::open(&self.path)?), destination)
     }
 }
+
+/// unpack a zip stream to destination directory
+pub fn unpack_zip<R: Read + Seek>(reader: R, destination: &mut Destination) -> Result<()> {
+    let mut rdr = ZipArchiveReader::new(reader)?;
+    for index in 0..rdr.len() {
+        let mut file = rdr.by_index(index)?;
+        let name = file.name().to_string();
+        if file.is_dir() {
+            destination.create_dir(&name)?;
+        } else {
+            destination.write(&name, &mut file)?;
+        }
+    }
+    Ok(())
+}
@@ -5,10 +5,25 @@
 #![doc = include_str!("../examples/extract-level.rs")]
 //!
 //! ```
+mod cache;
 mod data;
+mod error;
 mod formats;
+mod manifest;
+mod report;
 mod unpack;
+mod virtual_path;
+mod visitor;
 
-pub use data::{NoWalkList, UnpackStatus};
+pub use cache::Cache;
+pub use data::{
+    ArchiveNode, DetectionMode, DuplicateMode, LimitExceeded, Limits, NoWalkList, PathPolicy,
+    RewrittenEntry, UnpackLevel, UnpackResult, UnpackStatus,
+};
+pub use error::{ErrorSource, RunError, UnpackError};
 pub use formats::kinds::ArchiveKind;
+pub use manifest::{Manifest, ManifestEntry, ManifestHashes};
+pub use report::{Event, ReportFormat, ReportWriter};
 pub use unpack::DeepWalk;
+pub use virtual_path::VirtualPath;
+pub use visitor::{Entries, Entry, EntryMetadata, Visitor};
@@ -0,0 +1,173 @@
+//! Manifest of the extracted files with their content hashes
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::virtual_path::VirtualPath;
+
+/// Content hashes of the files of the [`Manifest`]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum ManifestHashes {
+    /// SHA-256 only
+    #[default]
+    Sha256,
+    /// SHA-256 and BLAKE3
+    Sha256Blake3,
+}
+
+/// File written to the unpack folder
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    pub virtual_path: VirtualPath,
+    /// Path of the file in the unpack folder
+    pub path: PathBuf,
+    pub size: u64,
+    /// Permission bits of the written file, `None` on platforms without them
+    pub mode: Option<u32>,
+    /// Modification time of the written file, in seconds since the epoch
+    pub mtime: Option<u64>,
+    /// Nanoseconds of the modification time, below the second
+    pub mtime_nanos: Option<u32>,
+    /// Hex encoded SHA-256 of the content
+    pub sha256: String,
+    /// Hex encoded BLAKE3 of the content, with [`ManifestHashes::Sha256Blake3`]
+    pub blake3: Option<String>,
+}
+
+/// Every file written by a run, in virtual path order
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    pub files: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    /// The manifest file of an unpack folder: `<unpack folder>.manifest.json`
+    /// next to it
+    #[must_use]
+    pub fn path(unpack_folder: &Path) -> PathBuf {
+        let mut name = unpack_folder
+            .file_name()
+            .unwrap_or_else(|| "deep_unpack".as_ref())
+            .to_os_string();
+        name.push(".manifest.json");
+        unpack_folder.with_file_name(name)
+    }
+
+    /// Write the manifest as a JSON document
+    ///
+    /// # Errors
+    /// When the file could not be written
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writeln!(writer)?;
+        writer.flush()
+    }
+}
+
+/// Hashes the content of a file while it is written
+pub struct ContentHasher {
+    sha256: Sha256,
+    /// boxed, its state is large
+    blake3: Option<Box<blake3::Hasher>>,
+}
+
+impl ContentHasher {
+    pub fn new(hashes: ManifestHashes) -> Self {
+        Self {
+            sha256: Sha256::new(),
+            blake3: (hashes == ManifestHashes::Sha256Blake3).then(Box::default),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.sha256.update(data);
+        if let Some(blake3) = &mut self.blake3 {
+            blake3.update(data);
+        }
+    }
+
+    /// The manifest entry of the written file, its mode and modification time
+    /// are read from the disk
+    pub fn finish(self, virtual_path: VirtualPath, path: &Path, size: u64) -> ManifestEntry {
+        let metadata = fs::metadata(path).ok();
+        let mtime = metadata.as_ref().and_then(modified);
+        ManifestEntry {
+            virtual_path,
+            path: path.to_path_buf(),
+            size,
+            mode: metadata.as_ref().and_then(file_mode),
+            mtime: mtime.map(|elapsed| elapsed.as_secs()),
+            mtime_nanos: mtime.map(|elapsed| elapsed.subsec_nanos()),
+            sha256: format!("{:x}", self.sha256.finalize()),
+            blake3: self
+                .blake3
+                .map(|blake3| blake3.finalize().to_hex().to_string()),
+        }
+    }
+}
+
+/// Modification time of a file since the epoch
+pub(crate) fn modified(metadata: &fs::Metadata) -> Option<Duration> {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+}
+
+#[cfg(unix)]
+#[allow(clippy::unnecessary_wraps)]
+fn file_mode(metadata: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode())
+}
+
+#[cfg(not(unix))]
+fn file_mode(_metadata: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+#[cfg(test)]
+mod test_manifest {
+
+    use super::*;
+
+    #[test]
+    fn can_hash_content() {
+        let mut hasher = ContentHasher::new(ManifestHashes::Sha256Blake3);
+        hasher.update(b"marram");
+        hasher.update(b" grass");
+        let entry = hasher.finish(
+            VirtualPath::new("a.zip").join("b.txt"),
+            Path::new("b.txt"),
+            12,
+        );
+
+        assert_eq!(
+            entry.sha256,
+            format!("{:x}", Sha256::digest(b"marram grass"))
+        );
+        assert_eq!(
+            entry.blake3,
+            Some(blake3::hash(b"marram grass").to_hex().to_string())
+        );
+        assert_eq!(entry.mode, None);
+    }
+
+    #[test]
+    fn can_place_manifest_next_to_unpack_folder() {
+        assert_eq!(
+            Manifest::path(Path::new("out").join("deep_unpack").as_path()),
+            Path::new("out").join("deep_unpack.manifest.json")
+        );
+    }
+}
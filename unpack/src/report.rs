@@ -0,0 +1,134 @@
+//! JSON reports of the unpacking runs
+use std::{
+    io::{self, Write},
+    path::Path,
+    sync::Mutex,
+};
+
+use serde::Serialize;
+
+use crate::{
+    data::{UnpackResult, UnpackStatus},
+    error::UnpackError,
+    formats::kinds::ArchiveKind,
+    virtual_path::VirtualPath,
+};
+
+/// How a [`ReportWriter`] writes the run
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// A single JSON document of the [`UnpackResult`], written when the run
+    /// is finished
+    Json,
+    /// One JSON [`Event`] per line, written as the run progresses
+    JsonLines,
+}
+
+/// Event of a run, a line of a [`ReportFormat::JsonLines`] report
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// The archive is about to be unpacked
+    ArchiveStarted {
+        virtual_path: &'a VirtualPath,
+        kind: ArchiveKind,
+        depth: u32,
+    },
+    /// A file entry was written to the disk
+    EntryWritten {
+        virtual_path: VirtualPath,
+        path: &'a Path,
+        bytes: u64,
+    },
+    /// The archive could not be unpacked, followed by its `ArchiveFinished`
+    Error {
+        virtual_path: &'a VirtualPath,
+        error: &'a UnpackError,
+    },
+    /// The archive and its nested archives were unpacked, or skipped as a
+    /// duplicate
+    ArchiveFinished { status: &'a UnpackStatus },
+    /// The run is finished
+    RunFinished { archives: usize, max_depth: u32 },
+}
+
+/// Receives the events of a run
+pub trait Report: Sync {
+    fn event(&self, event: &Event<'_>);
+}
+
+/// Writes the report of a run, created with `ReportWriter::new` and given to
+/// `DeepWalk::new().extract_with_report(..)`.
+///
+/// The events of the archives unpacked in parallel are interleaved, each
+/// line is a whole event.
+pub struct ReportWriter<W> {
+    format: ReportFormat,
+    state: Mutex<ReportState<W>>,
+}
+
+struct ReportState<W> {
+    writer: W,
+    /// first error of writing the report, the next events are dropped
+    err: Option<io::Error>,
+}
+
+impl<W: Write + Send> ReportWriter<W> {
+    pub fn new(writer: W, format: ReportFormat) -> Self {
+        Self {
+            format,
+            state: Mutex::new(ReportState { writer, err: None }),
+        }
+    }
+
+    /// Write the result of the run: the JSON document, or the `RunFinished`
+    /// event of a JSON lines report. Returns the writer.
+    ///
+    /// # Errors
+    /// The first error of writing the report
+    pub fn finish(self, result: &UnpackResult) -> io::Result<W> {
+        if self.format == ReportFormat::JsonLines {
+            self.write_line(&Event::RunFinished {
+                archives: result.statuses.len(),
+                max_depth: result.max_depth,
+            });
+        }
+        let mut state = self
+            .state
+            .into_inner()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        if let Some(err) = state.err {
+            return Err(err);
+        }
+        if self.format == ReportFormat::Json {
+            serde_json::to_writer_pretty(&mut state.writer, result)?;
+            writeln!(state.writer)?;
+        }
+        state.writer.flush()?;
+        Ok(state.writer)
+    }
+
+    fn write_line(&self, event: &Event<'_>) {
+        let mut state = self
+            .state
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        if state.err.is_some() {
+            return;
+        }
+        let result = serde_json::to_writer(&mut state.writer, event)
+            .map_err(io::Error::from)
+            .and_then(|()| writeln!(state.writer));
+        if let Err(err) = result {
+            state.err = Some(err);
+        }
+    }
+}
+
+impl<W: Write + Send> Report for ReportWriter<W> {
+    fn event(&self, event: &Event<'_>) {
+        if self.format == ReportFormat::JsonLines {
+            self.write_line(event);
+        }
+    }
+}
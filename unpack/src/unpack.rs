@@ -1,18 +1,39 @@
 //! packing archive files from folders
 use std::{
+    cmp::Reverse,
+    collections::{hash_map::Entry, HashMap, HashSet},
+    fs::{self, File},
+    io::{self, Read, Write},
     path::{Path, PathBuf},
-    sync::mpsc,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc, Arc, Mutex, MutexGuard, PoisonError,
+    },
+    time::{Duration, Instant},
 };
 
 use anyhow::{Context, Result};
 use derive_builder::Builder;
-use ignore::WalkBuilder;
+use ignore::{DirEntry, WalkBuilder};
 use lazy_static::lazy_static;
 use rayon::prelude::*;
+use sha2::{Digest, Sha256};
 
 use crate::{
-    data::{NoWalkList, UnpackStatus},
-    formats::kinds::ArchiveKind,
+    cache::{Cache, CacheEntry},
+    data::{
+        ArchiveNode, DetectionMode, DuplicateMode, Limits, NoWalkList, PathPolicy, UnpackLevel,
+        UnpackResult, UnpackStatus,
+    },
+    error::{ErrorSource, RunError, UnpackError},
+    formats::{
+        kinds::ArchiveKind,
+        path::{Destination, InMemoryArchive, SpilledArchive},
+    },
+    manifest::{ContentHasher, Manifest, ManifestEntry, ManifestHashes},
+    report::{Event, Report, ReportWriter},
+    virtual_path::VirtualPath,
+    visitor::{Entries, EntryMetadata, Visitor},
 };
 
 /// Skip searching archive file from a list of directories
@@ -22,11 +43,49 @@ lazy_static! {
     pub static ref NO_WALK_LIST: NoWalkList = serde_yaml::from_str(NO_WALK_YAML).unwrap();
 }
 
+/// SHA-256 of the archive file content
+type ContentHash = [u8; 32];
+
+/// Number of the runs of the process, for their spill folders
+static RUNS: AtomicU64 = AtomicU64::new(0);
+
 #[derive(Debug, Clone)]
 /// List of archive files that detected during the walk-on directories
 struct WalkResult {
     pub archive_kind: ArchiveKind,
     pub path_buf: PathBuf,
+    pub virtual_path: VirtualPath,
+    /// unpack level of the archive, archives of the root path are level 1
+    pub depth: u32,
+    /// content of a nested archive kept in memory, `path_buf` is the path it
+    /// would have been written to
+    pub data: Option<Vec<u8>>,
+    /// temporary copy of a nested archive of a visited archive, which is
+    /// unpacked from it
+    pub spilled: Option<PathBuf>,
+    /// content hashes and paths of the archives it was found in, outermost
+    /// first
+    pub containers: Vec<(ContentHash, PathBuf)>,
+}
+
+impl WalkResult {
+    /// The archive without its content kept in memory
+    fn without_data(&self) -> Self {
+        Self {
+            archive_kind: self.archive_kind,
+            path_buf: self.path_buf.clone(),
+            virtual_path: self.virtual_path.clone(),
+            depth: self.depth,
+            data: None,
+            spilled: self.spilled.clone(),
+            containers: self.containers.clone(),
+        }
+    }
+
+    /// The file the archive is read from, without its content in memory
+    fn file(&self) -> &Path {
+        self.spilled.as_deref().unwrap_or(&self.path_buf)
+    }
 }
 
 #[derive(Default, Builder, Debug)]
@@ -38,10 +97,32 @@ pub struct DeepWalk {
     pub unpack_folder: String,
     #[builder(default = "self.default_no_walk()")]
     pub no_walk: NoWalkList,
-    #[builder(default = "1", field(type = "u32"))]
-    pub unpack_level: u32,
+    #[builder(default = "UnpackLevel::Depth(1)")]
+    pub unpack_level: UnpackLevel,
     #[builder(default = "\"__${FILENAME}$__\".to_string()")]
     pub extract_template: String,
+    #[builder(default = "DetectionMode::Extension")]
+    pub detection: DetectionMode,
+    #[builder(default = "PathPolicy::Reject")]
+    pub path_policy: PathPolicy,
+    #[builder(default)]
+    pub limits: Limits,
+    /// Nested archives up to this size are unpacked from memory, without
+    /// writing them to the unpack folder
+    #[builder(default)]
+    pub in_memory_limit: Option<u64>,
+    /// Hash every file written to the unpack folder, the manifest is written
+    /// next to it and returned in the result
+    #[builder(default)]
+    pub manifest: Option<ManifestHashes>,
+    /// What to do with the archives which their content was already unpacked
+    /// in the run, by default only the first copy is unpacked
+    #[builder(default)]
+    pub duplicates: DuplicateMode,
+    /// Reuse the archives unpacked by the previous runs to the same folders,
+    /// see [`Cache`]
+    #[builder(default, setter(into, strip_option))]
+    pub cache_folder: Option<String>,
 }
 
 impl DeepWalk {
@@ -50,6 +131,116 @@ impl DeepWalk {
     pub fn new() -> DeepWalkBuilder {
         DeepWalkBuilder::default()
     }
+
+    /// Unpack the archives found under the folder, to the visitor when given
+    pub(crate) fn run(
+        &self,
+        visitor: Option<&dyn Visitor>,
+        report: Option<&dyn Report>,
+    ) -> UnpackResult {
+        let root_path = Path::new(&self.folder);
+        let unpack_folder = Path::new(&self.unpack_folder);
+
+        // first, find archive files from all the root path directories.
+        let walk_result =
+            DeepWalkBuilder::find_comppress_files(root_path, self.no_walk.clone(), self.detection);
+
+        // every unpacked archive goes on with the archives found in its folder,
+        // so a big archive never holds back the others
+        let run = Run {
+            config: self,
+            root_path,
+            unpack_folder,
+            max_depth: self.unpack_level.max_depth(),
+            total_bytes: Arc::new(AtomicU64::new(0)),
+            unpacked: Mutex::default(),
+            walked_files: Mutex::default(),
+            statuses: Mutex::default(),
+            archives: Mutex::default(),
+            duplicates: Mutex::default(),
+            spare: Mutex::default(),
+            manifest: Mutex::default(),
+            spill_folder: std::env::temp_dir().join(format!(
+                "deep-unpack-{}-{}",
+                std::process::id(),
+                RUNS.fetch_add(1, Ordering::Relaxed)
+            )),
+            cache: self.cache_folder.as_ref().map(Cache::new),
+            fingerprint: self.fingerprint(),
+            visitor,
+            report,
+        };
+        run.unpack_all(walk_result);
+        run.settle_duplicates();
+        run.resolve_duplicates();
+        if run.spill_folder.exists() {
+            if let Err(e) = fs::remove_dir_all(&run.spill_folder) {
+                log::debug!(
+                    "could not remove spill folder: {}. err: {}",
+                    run.spill_folder.display(),
+                    e
+                );
+            }
+        }
+
+        let statuses = run
+            .statuses
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner);
+        let max_depth = statuses
+            .iter()
+            .map(|status| status.depth)
+            .max()
+            .unwrap_or(0);
+        if max_depth == UnpackLevel::MAX_DEPTH && self.unpack_level == UnpackLevel::Exhausted {
+            log::info!("stopped unpacking at the maximum depth: {}", max_depth);
+        }
+        UnpackResult {
+            statuses,
+            tree: provenance_tree(
+                run.archives
+                    .into_inner()
+                    .unwrap_or_else(PoisonError::into_inner),
+            ),
+            max_depth,
+            // nothing is written to the disk with a visitor
+            manifest: self.manifest.filter(|_| visitor.is_none()).map(|_| {
+                let mut files = run
+                    .manifest
+                    .into_inner()
+                    .unwrap_or_else(PoisonError::into_inner);
+                files.sort_by(|a, b| a.virtual_path.cmp(&b.virtual_path));
+                Manifest { files }
+            }),
+        }
+    }
+
+    /// Hash of the configuration which shapes the files unpacked from an
+    /// archive, a part of its cache key. The unpack level and the manifest
+    /// do not change the files of an archive.
+    fn fingerprint(&self) -> ContentHash {
+        Sha256::digest(format!(
+            "{}:{}:{:?}:{:?}:{:?}:{:?}",
+            env!("CARGO_PKG_VERSION"),
+            self.extract_template,
+            self.detection,
+            self.path_policy,
+            self.limits,
+            self.in_memory_limit,
+        ))
+        .into()
+    }
+
+    /// Unpack the archives to the unpack folder, then write the manifest
+    fn extract(&self, report: Option<&dyn Report>) -> Result<UnpackResult, RunError> {
+        let result = self.run(None, report);
+        if let Some(manifest) = &result.manifest {
+            manifest
+                .write(&Manifest::path(Path::new(&self.unpack_folder)))
+                .map_err(|e| RunError::Manifest(ErrorSource::from(anyhow::Error::new(e))))?;
+        }
+        Ok(result)
+    }
 }
 
 impl DeepWalkBuilder {
@@ -58,167 +249,999 @@ impl DeepWalkBuilder {
         NO_WALK_LIST.clone()
     }
 
-    pub fn extract(&self) -> Result<Vec<UnpackStatus>> {
-        let unpack_config = self.build()?;
-
-        let root_path = Path::new(&unpack_config.folder);
-        let unpack_folder = Path::new(&unpack_config.unpack_folder);
+    /// Unpack the archives found under the folder to the unpack folder
+    ///
+    /// # Errors
+    /// [`RunError::Config`] when the configuration is invalid and
+    /// [`RunError::Manifest`] when the manifest could not be written, the
+    /// errors of the archives are reported in their statuses
+    pub fn extract(&self) -> Result<UnpackResult, RunError> {
+        self.config()?.extract(None)
+    }
 
-        // first, find archive files from all the root path directories.
-        let walk_result = Self::find_comppress_files(&root_path, unpack_config.no_walk.clone());
+    /// Unpack the archives like [`DeepWalkBuilder::extract`], reporting the
+    /// run to the report writer. `ReportWriter::finish` writes the result.
+    ///
+    /// # Errors
+    /// [`RunError::Config`] when the configuration is invalid and
+    /// [`RunError::Manifest`] when the manifest could not be written
+    pub fn extract_with_report<W: Write + Send>(
+        &self,
+        report: &ReportWriter<W>,
+    ) -> Result<UnpackResult, RunError> {
+        self.config()?.extract(Some(report))
+    }
 
-        if walk_result.is_empty() {
-            return Ok(vec![]);
-        }
+    /// Give the file entries of the archives found under the folder to the
+    /// visitor, nothing is extracted and the unpack folder is not used. Nested
+    /// archives are unpacked from memory, the ones larger than the in-memory
+    /// limit and the RAR and self-extracting archives from a temporary copy
+    /// which is removed at the end of the run.
+    ///
+    /// # Errors
+    /// [`RunError::Config`] when the configuration is invalid
+    pub fn visit(&self, visitor: &dyn Visitor) -> Result<UnpackResult, RunError> {
+        Ok(self.config()?.run(Some(visitor), None))
+    }
 
-        let mut result: Vec<UnpackStatus> = vec![];
-        result.extend(Self::parallel_unpack(
-            walk_result,
-            Some(root_path),
-            unpack_folder,
-            &unpack_config.extract_template,
-        ));
-
-        // if a deep level is bigger than 1, search in the extracted folder if there are
-        // more archive files. If yes, extract them also
-        let mut unpacked_files: Vec<String> = vec![];
-        for _ in 2..=unpack_config.unpack_level {
-            let walk_result =
-                Self::find_comppress_files(&unpack_folder, unpack_config.no_walk.clone())
-                    .iter()
-                    .filter(|f| {
-                        // make sure that we are not unpacking the same file twice
-                        let path_str = f.path_buf.display().to_string();
-                        if unpacked_files.contains(&path_str) {
-                            false
-                        } else {
-                            unpacked_files.push(path_str);
-                            true
-                        }
-                    })
-                    .cloned()
-                    .collect::<Vec<_>>();
+    /// Iterate over the file entries of the archives found under the folder,
+    /// see [`Entries`]
+    ///
+    /// # Errors
+    /// [`RunError::Config`] when the configuration is invalid
+    pub fn entries(&self) -> Result<Entries, RunError> {
+        Ok(Entries::new(self.config()?))
+    }
 
-            if walk_result.is_empty() {
-                break;
-            }
-            result.extend(Self::parallel_unpack(
-                walk_result,
-                None,
-                unpack_folder,
-                &unpack_config.extract_template,
-            ));
-        }
-        Ok(result)
+    /// Build the configuration of a run
+    fn config(&self) -> Result<DeepWalk, RunError> {
+        self.build()
+            .map_err(|e| RunError::Config(ErrorSource::from(anyhow::Error::new(e))))
     }
 
     /// Return list of archive files from a given folder
-    fn find_comppress_files<P: AsRef<Path>>(path: &P, no_walk: NoWalkList) -> Vec<WalkResult> {
+    fn find_comppress_files<P: AsRef<Path> + ?Sized>(
+        path: &P,
+        no_walk: NoWalkList,
+        detection: DetectionMode,
+    ) -> Vec<WalkResult> {
         let (tx, rx) = mpsc::channel();
-        WalkBuilder::new(path)
-            .filter_entry(move |entry| {
-                if let Some(ep) = entry.path().to_str() {
-                    if no_walk.ignores.iter().any(|item| item.is_match(ep)) {
-                        return false;
-                    }
-                }
-                true
-            })
-            .hidden(false)
-            .git_ignore(true)
+        walk_builder(path, no_walk, None)
             .threads(num_cpus::get())
             .build_parallel()
             .run(move || {
                 let tx = tx.clone();
                 Box::new(move |result| {
-                    if let Ok(de) = result {
-                        let metadata = match de.metadata() {
-                            Ok(m) => m,
-                            Err(e) => {
-                                log::info!("could not get dir entry medatada. {}", e);
-                                return ignore::WalkState::Continue;
-                            }
-                        };
-
-                        if metadata.is_dir() {
-                            return ignore::WalkState::Continue;
-                        }
-
-                        // check if the file is comppreesed file
-                        let path_buf = de.path().to_path_buf();
-                        if let Some(archive_kind) = ArchiveKind::for_path(&path_buf) {
-                            if let Err(err) = tx.send(WalkResult {
-                                archive_kind,
-                                path_buf,
-                            }) {
-                                log::info!(
-                                    "could not send extract status struct to channel. {}",
-                                    err
-                                );
-                            }
+                    if let Some(walk_result) = detect_archive(result, detection, 1) {
+                        if let Err(err) = tx.send(walk_result) {
+                            log::info!("could not send extract status struct to channel. {}", err);
                         }
                     }
                     ignore::WalkState::Continue
                 })
             });
-        rx.iter().into_iter().collect::<Vec<WalkResult>>()
+        rx.iter().collect::<Vec<WalkResult>>()
     }
+}
+
+/// State shared by the unpack tasks of a run
+struct Run<'a> {
+    config: &'a DeepWalk,
+    root_path: &'a Path,
+    unpack_folder: &'a Path,
+    max_depth: u32,
+    /// uncompressed bytes of all the unpacked archives, for the total limit
+    total_bytes: Arc<AtomicU64>,
+    /// content of all the unpacked archives with the copy which was
+    /// unpacked, the same bytes are never unpacked twice (copies, recursive
+    /// archives)
+    unpacked: Mutex<HashMap<ContentHash, WalkResult>>,
+    /// make sure that we are not unpacking the same file twice
+    walked_files: Mutex<HashSet<PathBuf>>,
+    statuses: Mutex<Vec<UnpackStatus>>,
+    /// every archive of the run without its children, for the provenance tree
+    archives: Mutex<Vec<ArchiveNode>>,
+    /// archives which their content was already unpacked, resolved once all
+    /// the archives are unpacked
+    duplicates: Mutex<Vec<(ContentHash, WalkResult, UnpackStatus)>>,
+    /// content of the duplicates kept in memory, one copy each, for the
+    /// duplicates which turn out to be the first copy
+    spare: Mutex<HashMap<ContentHash, Vec<u8>>>,
+    /// the files written by all the archives, with a manifest
+    manifest: Mutex<Vec<ManifestEntry>>,
+    /// temporary folder of the nested archives of the visited archives which
+    /// cannot be kept in memory, removed at the end of the run
+    spill_folder: PathBuf,
+    cache: Option<Cache>,
+    /// configuration part of the cache keys
+    fingerprint: ContentHash,
+    /// the file entries are given to the visitor instead of being written
+    visitor: Option<&'a dyn Visitor>,
+    report: Option<&'a dyn Report>,
+}
+
+impl<'a> Run<'a> {
+    /// Unpack the archives found in a folder in parallel, skipping the archives
+    /// which their content was already unpacked.
+    ///
+    /// The first path wins between archives with the same content found in
+    /// the same folder, otherwise the archive found first wins until
+    /// [`Run::settle_duplicates`].
+    fn unpack_all(&self, mut walk_result: Vec<WalkResult>) {
+        {
+            let mut walked_files = lock(&self.walked_files);
+            walk_result.retain(|f| walked_files.insert(f.path_buf.clone()));
+        }
+        let Some(depth) = walk_result.first().map(|f| f.depth) else {
+            return;
+        };
 
-    /// unpack list of [`WalkResult`] in parallel
-    fn parallel_unpack(
-        walk_result: Vec<WalkResult>,
-        root_path: Option<&Path>,
-        unpack_root_folder: &Path,
-        extract_template: &str,
-    ) -> Vec<UnpackStatus> {
-        walk_result
+        walk_result.sort_by(|a, b| a.path_buf.cmp(&b.path_buf));
+        let hashes = walk_result
             .par_iter()
-            .map(|archive_path| {
-                let mut archive = archive_path.archive_kind.new(&archive_path.path_buf);
+            .map(|archive_path| match &archive_path.data {
+                Some(data) => Ok(Sha256::digest(data).into()),
+                None => content_hash(archive_path.file()),
+            })
+            .collect::<Vec<_>>();
 
-                let file_unpack_path = match root_path {
-                    Some(p) => match archive_path.path_buf.strip_prefix(p) {
-                        Ok(a) => unpack_root_folder.join(a),
-                        Err(e) => {
+        let mut duplicates = vec![];
+        let mut new_archives = vec![];
+        {
+            let mut unpacked = lock(&self.unpacked);
+            for (archive_path, hash) in walk_result.into_iter().zip(hashes) {
+                match hash {
+                    Ok(hash) if self.config.duplicates == DuplicateMode::Unpack => {
+                        match archive_path
+                            .containers
+                            .iter()
+                            .find(|(container, _)| *container == hash)
+                        {
+                            Some((_, container)) => {
+                                log::debug!(
+                                    "skip archive: {} with the same content as its container: {}",
+                                    archive_path.path_buf.display(),
+                                    container.display()
+                                );
+                                let status = UnpackStatus {
+                                    duplicate_of: Some(container.clone()),
+                                    ..new_status(&archive_path)
+                                };
+                                duplicates.push((hash, archive_path.without_data(), status));
+                            }
+                            None => new_archives.push((archive_path, Some(hash))),
+                        }
+                    }
+                    Ok(hash) => match unpacked.entry(hash) {
+                        Entry::Occupied(first) => {
                             log::debug!(
-                                "could not strip: {} with prefix: {}. err: {}",
+                                "skip archive: {} with the same content as: {}",
                                 archive_path.path_buf.display(),
-                                p.display(),
-                                e
+                                first.get().path_buf.display()
                             );
-                            unpack_root_folder.to_path_buf()
+                            let status = new_status(&archive_path);
+                            let mut archive_path = archive_path;
+                            // the data is not needed to link the duplicate
+                            if let Some(data) = archive_path.data.take() {
+                                lock(&self.spare).entry(hash).or_insert(data);
+                            }
+                            duplicates.push((hash, archive_path, status));
+                        }
+                        Entry::Vacant(entry) => {
+                            entry.insert(archive_path.without_data());
+                            new_archives.push((archive_path, Some(hash)));
                         }
                     },
-                    None => archive_path.path_buf.clone(),
-                };
+                    Err(e) => {
+                        log::debug!(
+                            "could not hash archive: {}. err: {}",
+                            archive_path.path_buf.display(),
+                            e
+                        );
+                        new_archives.push((archive_path, None));
+                    }
+                }
+            }
+        }
+        lock(&self.duplicates).extend(duplicates);
+
+        self.unpack_each(&new_archives, depth > 1);
+    }
+
+    /// Unpack the archives in parallel, the nested archives one after the
+    /// other with a visitor: it gets the calls of a root archive in order
+    fn unpack_each(&self, archives: &[(WalkResult, Option<ContentHash>)], nested: bool) {
+        if self.visitor.is_some() && nested {
+            archives
+                .iter()
+                .for_each(|(archive_path, hash)| self.unpack(archive_path, hash.as_ref()));
+        } else {
+            archives
+                .par_iter()
+                .for_each(|(archive_path, hash)| self.unpack(archive_path, hash.as_ref()));
+        }
+    }
+
+    /// Unpack the archive, then the archives found in its folder when the
+    /// unpack level allows it. The nested archives do not wait for the other
+    /// archives of the same level.
+    fn unpack(&self, archive_path: &WalkResult, hash: Option<&ContentHash>) {
+        if let Some(visitor) = self.visitor {
+            visitor.on_archive_enter(&archive_path.virtual_path, archive_path.archive_kind);
+        }
+        if let Some(report) = self.report {
+            report.event(&Event::ArchiveStarted {
+                virtual_path: &archive_path.virtual_path,
+                kind: archive_path.archive_kind,
+                depth: archive_path.depth,
+            });
+        }
+        let (status, in_memory, spilled) = self.unpack_archive(archive_path, hash);
+
+        let mut containers = archive_path.containers.clone();
+        if let Some(hash) = hash {
+            containers.push((*hash, archive_path.path_buf.clone()));
+        }
+        let mut nested = in_memory
+            .into_iter()
+            .map(|archive| WalkResult {
+                archive_kind: archive.kind,
+                path_buf: archive.path,
+                virtual_path: archive.virtual_path,
+                depth: archive_path.depth + 1,
+                data: Some(archive.data),
+                spilled: None,
+                containers: containers.clone(),
+            })
+            .collect::<Vec<_>>();
+        nested.extend(spilled.into_iter().map(|archive| WalkResult {
+            archive_kind: archive.kind,
+            path_buf: archive.path,
+            virtual_path: archive.virtual_path,
+            depth: archive_path.depth + 1,
+            data: None,
+            spilled: Some(archive.file),
+            containers: containers.clone(),
+        }));
+
+        if status.err.is_none() && archive_path.depth < self.max_depth {
+            let mut walk_result = match &status.extract_to {
+                Some(extract_to) => find_nested_compress_files(
+                    extract_to,
+                    self.config.no_walk.clone(),
+                    self.config.detection,
+                    &self.config.extract_template,
+                    archive_path.depth + 1,
+                )
+                .into_iter()
+                .map(|nested| WalkResult {
+                    virtual_path: archive_path.virtual_path.join(
+                        nested
+                            .path_buf
+                            .strip_prefix(extract_to)
+                            .unwrap_or(&nested.path_buf),
+                    ),
+                    containers: containers.clone(),
+                    ..nested
+                })
+                .collect(),
+                None => vec![],
+            };
+            walk_result.extend(nested);
+            self.unpack_all(walk_result);
+        } else {
+            nested.iter().for_each(|nested| self.keep_content(nested));
+        }
+
+        if let Some(visitor) = self.visitor {
+            visitor.on_archive_leave(&archive_path.virtual_path, &status);
+        }
+        if status.err.is_some() {
+            self.keep_content(archive_path);
+        }
+        if let Some(report) = self.report {
+            if let Some(error) = &status.err {
+                report.event(&Event::Error {
+                    virtual_path: &status.virtual_path,
+                    error,
+                });
+            }
+            report.event(&Event::ArchiveFinished { status: &status });
+        }
+        lock(&self.archives).push(archive_node(archive_path, status.extract_to.clone()));
+        lock(&self.statuses).push(status);
+    }
+
+    /// Keep the content of a nested archive kept in memory or spilled which
+    /// was not unpacked: write it to the path it would have been extracted
+    /// to, or give it to the visitor as a file entry of its container
+    fn keep_content(&self, archive_path: &WalkResult) {
+        let mut reader: Box<dyn Read> = match (&archive_path.data, &archive_path.spilled) {
+            (Some(data), _) => Box::new(data.as_slice()),
+            (None, Some(spilled)) => match File::open(spilled) {
+                Ok(file) => Box::new(file),
+                Err(e) => {
+                    log::debug!("could not open: {}. err: {}", spilled.display(), e);
+                    return;
+                }
+            },
+            (None, None) => return,
+        };
+
+        if let Some(visitor) = self.visitor {
+            let metadata = EntryMetadata {
+                archive: archive_path.virtual_path.parent().unwrap_or_default(),
+                depth: archive_path.depth.saturating_sub(1),
+            };
+            if let Err(e) = visitor.on_entry(&archive_path.virtual_path, &metadata, &mut reader) {
+                log::debug!(
+                    "visitor failed on archive: {}. err: {}",
+                    archive_path.virtual_path,
+                    e
+                );
+            }
+            return;
+        }
 
-                let unpack_folder = match split_file_by_name(file_unpack_path.as_path()) {
-                    Ok((file_name, folder)) => {
-                        folder.join(extract_template.replace("{FILENAME}", &file_name))
+        let path = &archive_path.path_buf;
+        let mut hasher = self.config.manifest.map(ContentHasher::new);
+        let written = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| File::create(path))
+            .and_then(|mut file| {
+                let mut bytes = 0;
+                let mut buf = vec![0_u8; 64 * 1024];
+                loop {
+                    let read = reader.read(&mut buf)?;
+                    if read == 0 {
+                        return Ok(bytes);
                     }
-                    Err(e) => {
-                        log::debug!("ould not split file by name. err: {}", e);
-                        unpack_root_folder.to_path_buf()
+                    file.write_all(&buf[..read])?;
+                    if let Some(hasher) = &mut hasher {
+                        hasher.update(&buf[..read]);
+                    }
+                    bytes += read as u64;
+                }
+            });
+        match written {
+            Ok(bytes) => {
+                log::debug!("keep archive which was not unpacked: {}", path.display());
+                if let Some(report) = self.report {
+                    report.event(&Event::EntryWritten {
+                        virtual_path: archive_path.virtual_path.clone(),
+                        path,
+                        bytes,
+                    });
+                }
+                if let Some(hasher) = hasher {
+                    lock(&self.manifest).push(hasher.finish(
+                        archive_path.virtual_path.clone(),
+                        path,
+                        bytes,
+                    ));
+                }
+            }
+            Err(e) => log::debug!("could not write: {}. err: {}", path.display(), e),
+        }
+    }
+
+    /// Make the copy found at the lowest depth, then with the lowest virtual
+    /// path, the first copy of every content, whatever the order the archives
+    /// were unpacked in.
+    ///
+    /// A duplicate which turns out to be the first copy is unpacked, and the
+    /// copy which was unpacked in its place becomes a duplicate, its extract
+    /// folder and the archives found in it are dropped. The visitor and the
+    /// report were already given the dropped archives. The unpacked
+    /// duplicates find archives of their own, so it goes on until every first
+    /// copy is unpacked.
+    fn settle_duplicates(&self) {
+        loop {
+            let mut copies = lock(&self.unpacked)
+                .iter()
+                .map(|(hash, first)| (*hash, first.clone(), true))
+                .collect::<Vec<_>>();
+            copies.extend(
+                lock(&self.duplicates)
+                    .iter()
+                    .map(|(hash, duplicate, _)| (*hash, duplicate.clone(), false)),
+            );
+            // the containers come before their members
+            copies.sort_by(|(_, a, _), (_, b, _)| {
+                (a.depth, &a.virtual_path).cmp(&(b.depth, &b.virtual_path))
+            });
+
+            let mut firsts = HashMap::new();
+            let mut superseded = vec![];
+            for (hash, archive_path, unpacked) in copies {
+                if superseded.iter().any(|(_, dropped): &(_, WalkResult)| {
+                    archive_path.virtual_path.is_inside(&dropped.virtual_path)
+                }) {
+                    continue;
+                }
+                match firsts.entry(hash) {
+                    Entry::Vacant(entry) => {
+                        entry.insert((archive_path, unpacked));
                     }
+                    Entry::Occupied(_) if unpacked => superseded.push((hash, archive_path)),
+                    Entry::Occupied(_) => {}
+                }
+            }
+            if superseded.is_empty() {
+                return;
+            }
+
+            let mut missed = vec![];
+            for (hash, archive_path) in superseded {
+                let Some((first, false)) = firsts.remove(&hash) else {
+                    continue;
                 };
+                log::debug!(
+                    "unpack archive: {} in place of its copy: {}",
+                    first.path_buf.display(),
+                    archive_path.path_buf.display()
+                );
+                let first = self.take_duplicate(hash, &first);
+                self.supersede(hash, archive_path, &first);
+                lock(&self.unpacked).insert(hash, first.without_data());
+                missed.push((first, Some(hash)));
+            }
+            if missed.is_empty() {
+                return;
+            }
+            self.unpack_each(&missed, true);
+        }
+    }
 
-                match archive.unpack(&unpack_folder) {
-                    Ok(()) => UnpackStatus {
-                        archive_file: archive_path.path_buf.clone(),
-                        extract_to: Some(unpack_folder),
-                        err: None,
-                    },
-                    Err(e) => UnpackStatus {
-                        archive_file: archive_path.path_buf.clone(),
-                        extract_to: None,
-                        err: Some(format!("{}", e)),
-                    },
+    /// Take the duplicate out of the duplicates to unpack it, with its content
+    /// when it was kept in memory
+    fn take_duplicate(&self, hash: ContentHash, duplicate: &WalkResult) -> WalkResult {
+        lock(&self.duplicates)
+            .retain(|(_, archive_path, _)| archive_path.virtual_path != duplicate.virtual_path);
+        WalkResult {
+            data: if duplicate.file().is_file() {
+                None
+            } else {
+                lock(&self.spare).get(&hash).cloned()
+            },
+            ..duplicate.without_data()
+        }
+    }
+
+    /// Turn an unpacked archive into a duplicate of its first copy, dropping
+    /// its extract folder and the archives found in it
+    fn supersede(&self, hash: ContentHash, archive_path: WalkResult, first: &WalkResult) {
+        let is_dropped = |virtual_path: &VirtualPath| {
+            *virtual_path == archive_path.virtual_path
+                || virtual_path.is_inside(&archive_path.virtual_path)
+        };
+        lock(&self.unpacked).retain(|_, unpacked| !is_dropped(&unpacked.virtual_path));
+        lock(&self.duplicates).retain(|(_, duplicate, _)| !is_dropped(&duplicate.virtual_path));
+        lock(&self.archives).retain(|archive| !is_dropped(&archive.virtual_path));
+        lock(&self.manifest).retain(|file| !is_dropped(&file.virtual_path));
+        let dropped = {
+            let mut statuses = lock(&self.statuses);
+            let (dropped, kept) = std::mem::take(&mut *statuses)
+                .into_iter()
+                .partition::<Vec<_>, _>(|status| is_dropped(&status.virtual_path));
+            *statuses = kept;
+            dropped
+        };
+
+        let bytes = dropped
+            .iter()
+            .map(|status| status.uncompressed_bytes)
+            .sum::<u64>();
+        // the update never fails, the closure always returns a value
+        let _ = self
+            .total_bytes
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |total| {
+                Some(total.saturating_sub(bytes))
+            });
+        let own = dropped
+            .into_iter()
+            .find(|status| status.virtual_path == archive_path.virtual_path);
+        if let Some(extract_to) = own.as_ref().and_then(|status| status.extract_to.as_ref()) {
+            if let Err(e) = fs::remove_dir_all(extract_to) {
+                log::debug!(
+                    "could not remove extract folder: {}. err: {}",
+                    extract_to.display(),
+                    e
+                );
+            }
+        }
+
+        // a duplicate kept in memory may turn out to be the first copy again
+        if !archive_path.file().is_file() {
+            let data = first.data.clone().or_else(|| fs::read(first.file()).ok());
+            if let Some(data) = data {
+                lock(&self.spare).entry(hash).or_insert(data);
+            }
+        }
+        let status = UnpackStatus {
+            compressed_bytes: own.map_or(0, |status| status.compressed_bytes),
+            ..new_status(&archive_path)
+        };
+        lock(&self.duplicates).push((hash, archive_path, status));
+    }
+
+    /// Point the duplicates to the extract folder of their first copy, and
+    /// link it with [`DuplicateMode::Hardlink`] and [`DuplicateMode::Symlink`].
+    /// The deepest duplicates are linked first, the folders linked by the
+    /// others may contain their links.
+    fn resolve_duplicates(&self) {
+        let mut duplicates = std::mem::take(&mut *lock(&self.duplicates));
+        duplicates.sort_by_key(|(_, archive_path, _)| Reverse(archive_path.depth));
+        let firsts = std::mem::take(&mut *lock(&self.unpacked));
+        let extract_folders = lock(&self.statuses)
+            .iter()
+            .filter_map(|status| Some((status.archive_file.clone(), status.extract_to.clone()?)))
+            .collect::<HashMap<_, _>>();
+
+        for (hash, archive_path, mut status) in duplicates {
+            if let Some(first) = firsts.get(&hash) {
+                status.duplicate_of = Some(first.path_buf.clone());
+            }
+            status.canonical_extract_to = status
+                .duplicate_of
+                .as_ref()
+                .and_then(|first| extract_folders.get(first))
+                .cloned();
+            if let Some(canonical) = status.canonical_extract_to.as_ref().filter(|_| {
+                matches!(
+                    self.config.duplicates,
+                    DuplicateMode::Hardlink | DuplicateMode::Symlink
+                )
+            }) {
+                let link = self.extract_folder(&archive_path);
+                if link.starts_with(canonical) {
+                    // an archive which contains itself
+                    log::debug!(
+                        "skip linking archive: {} inside its first copy: {}",
+                        archive_path.path_buf.display(),
+                        canonical.display()
+                    );
+                } else {
+                    match link_folder(canonical, &link, self.config.duplicates) {
+                        Ok(()) => status.extract_to = Some(link),
+                        Err(e) => status.err = Some(UnpackError::from(anyhow::Error::new(e))),
+                    }
                 }
-            })
-            .collect::<Vec<_>>()
+            }
+
+            if let Some(report) = self.report {
+                if let Some(error) = &status.err {
+                    report.event(&Event::Error {
+                        virtual_path: &status.virtual_path,
+                        error,
+                    });
+                }
+                report.event(&Event::ArchiveFinished { status: &status });
+            }
+            lock(&self.archives).push(archive_node(&archive_path, status.extract_to.clone()));
+            lock(&self.statuses).push(status);
+        }
+    }
+
+    /// Folder of the extract template for a [`WalkResult`]
+    fn extract_folder(&self, archive_path: &WalkResult) -> PathBuf {
+        // the archives of the root path are unpacked to the same structure in
+        // the unpack folder, nested archives next to themselves
+        let file_unpack_path = if archive_path.depth == 1 {
+            match archive_path.path_buf.strip_prefix(self.root_path) {
+                Ok(a) => self.unpack_folder.join(a),
+                Err(e) => {
+                    log::debug!(
+                        "could not strip: {} with prefix: {}. err: {}",
+                        archive_path.path_buf.display(),
+                        self.root_path.display(),
+                        e
+                    );
+                    self.unpack_folder.to_path_buf()
+                }
+            }
+        } else {
+            archive_path.path_buf.clone()
+        };
+
+        match split_file_by_name(file_unpack_path.as_path()) {
+            Ok((file_name, folder)) => folder.join(
+                self.config
+                    .extract_template
+                    .replace("{FILENAME}", &file_name),
+            ),
+            Err(e) => {
+                log::debug!("ould not split file by name. err: {}", e);
+                self.unpack_folder.to_path_buf()
+            }
+        }
+    }
+
+    /// Cache key of an archive content with the configuration of the run,
+    /// `None` without a cache or with a visitor
+    fn cache_key(&self, hash: Option<&ContentHash>) -> Option<String> {
+        self.cache.as_ref().filter(|_| self.visitor.is_none())?;
+        let mut hasher = Sha256::new();
+        hasher.update(hash?);
+        hasher.update(self.fingerprint);
+        Some(format!("{:x}", hasher.finalize()))
+    }
+
+    /// Status of an archive which a previous run unpacked to the same folder,
+    /// its files are added to the manifest. `None` when it has to be unpacked.
+    fn cached_status(&self, archive_path: &WalkResult, key: &str) -> Option<UnpackStatus> {
+        let entry = self
+            .cache
+            .as_ref()?
+            .get(key, &self.extract_folder(archive_path))?;
+        // the files of an entry recorded without the manifest have no BLAKE3
+        if self.config.manifest == Some(ManifestHashes::Sha256Blake3)
+            && entry.files.iter().any(|file| file.blake3.is_none())
+        {
+            return None;
+        }
+        log::debug!(
+            "archive: {} is cached in: {}",
+            archive_path.path_buf.display(),
+            entry.extract_to.display()
+        );
+        self.total_bytes
+            .fetch_add(entry.uncompressed_bytes, Ordering::Relaxed);
+        if self.config.manifest.is_some() {
+            let extract_to = &entry.extract_to;
+            lock(&self.manifest).extend(entry.files.into_iter().map(|file| {
+                ManifestEntry {
+                    virtual_path: archive_path
+                        .virtual_path
+                        .join(file.path.strip_prefix(extract_to).unwrap_or(&file.path)),
+                    ..file
+                }
+            }));
+        }
+        Some(UnpackStatus {
+            extract_to: Some(entry.extract_to),
+            rewritten_entries: entry.rewritten_entries,
+            entries_extracted: entry.entries_extracted,
+            entries_skipped: entry.entries_skipped,
+            uncompressed_bytes: entry.uncompressed_bytes,
+            cached: true,
+            ..new_status(archive_path)
+        })
+    }
+
+    /// unpack a [`WalkResult`] to the folder of the extract template, or to
+    /// the visitor, returns the nested archives which were kept in memory
+    fn unpack_archive(
+        &self,
+        archive_path: &WalkResult,
+        hash: Option<&ContentHash>,
+    ) -> (UnpackStatus, Vec<InMemoryArchive>, Vec<SpilledArchive>) {
+        let started = Instant::now();
+        let cache_key = self.cache_key(hash);
+        if let Some(status) = cache_key
+            .as_ref()
+            .and_then(|key| self.cached_status(archive_path, key))
+        {
+            return (
+                UnpackStatus {
+                    elapsed: started.elapsed(),
+                    ..status
+                },
+                vec![],
+                vec![],
+            );
+        }
+
+        let mut status = new_status(archive_path);
+        let (mut destination, in_memory_limit) = match self.visitor {
+            // the entries of a visited archive are under its virtual path, its
+            // nested archives are unpacked from memory or from a spilled file
+            Some(visitor) => (
+                Destination::new(&archive_path.path_buf, self.config.path_policy)
+                    .with_visitor(visitor, archive_path.depth)
+                    .with_spill(&self.spill_folder),
+                Some(self.config.in_memory_limit.unwrap_or(u64::MAX)),
+            ),
+            None => (
+                Destination::new(&self.extract_folder(archive_path), self.config.path_policy),
+                self.config.in_memory_limit,
+            ),
+        };
+        destination = destination
+            .with_virtual_path(archive_path.virtual_path.clone())
+            .with_report(self.report)
+            // the cache entries record the files even without a manifest
+            .with_manifest(
+                self.config
+                    .manifest
+                    .or_else(|| cache_key.as_ref().map(|_| ManifestHashes::Sha256)),
+            )
+            .with_limits(
+                self.config.limits,
+                status.compressed_bytes,
+                self.total_bytes.clone(),
+            );
+        // the nested archives are kept in memory only when they are unpacked
+        if let Some(limit) = in_memory_limit.filter(|_| archive_path.depth < self.max_depth) {
+            destination = destination.with_in_memory(limit, self.config.detection);
+        }
+
+        let result = match &archive_path.data {
+            Some(data) => archive_path.archive_kind.unpack_bytes(
+                &archive_path.path_buf,
+                data,
+                &mut destination,
+            ),
+            None => archive_path
+                .archive_kind
+                .new(archive_path.file())
+                .unpack_to(&mut destination),
+        };
+        let in_memory = destination.take_in_memory();
+        let spilled = destination.take_spilled();
+        let files = destination.take_manifest();
+        // the entries written before a failure are kept and reported with it
+        status.extract_to = (!destination.is_visiting()
+            && (result.is_ok() || destination.directory().exists()))
+        .then(|| destination.directory().to_path_buf());
+        if let Err(e) = result {
+            let err = UnpackError::from(e);
+            if let UnpackError::LimitExceeded(limit) = &err {
+                status.limit_exceeded = Some(*limit);
+            }
+            status.err = Some(err);
+        }
+        status.entries_extracted = destination.extracted();
+        status.entries_skipped = destination.skipped();
+        status.uncompressed_bytes = destination.archive_bytes();
+        status.rewritten_entries = destination.into_rewritten();
+
+        // the nested archives kept in memory are not in the extract folder
+        if let (Some(cache), Some(key), Some(extract_to), true) = (
+            &self.cache,
+            &cache_key,
+            &status.extract_to,
+            in_memory.is_empty() && status.err.is_none(),
+        ) {
+            let entry = CacheEntry {
+                extract_to: extract_to.clone(),
+                entries_extracted: status.entries_extracted,
+                entries_skipped: status.entries_skipped,
+                uncompressed_bytes: status.uncompressed_bytes,
+                rewritten_entries: status.rewritten_entries.clone(),
+                files: files.clone(),
+            };
+            if let Err(e) = cache.put(key, &entry) {
+                log::debug!(
+                    "could not cache archive: {}. err: {}",
+                    archive_path.path_buf.display(),
+                    e
+                );
+            }
+        }
+        if self.config.manifest.is_some() {
+            lock(&self.manifest).extend(files);
+        }
+        status.elapsed = started.elapsed();
+        (status, in_memory, spilled)
+    }
+}
+
+/// Return list of archive files from an extracted folder. The folder is
+/// walked by the calling unpack task, the tasks already run in parallel.
+/// The extract folders of the nested archives left by a previous run are not
+/// walked, the nested archives are unpacked to them again.
+fn find_nested_compress_files(
+    path: &Path,
+    no_walk: NoWalkList,
+    detection: DetectionMode,
+    extract_template: &str,
+    depth: u32,
+) -> Vec<WalkResult> {
+    walk_builder(path, no_walk, Some(extract_template.to_string()))
+        .build()
+        .filter_map(|result| detect_archive(result, detection, depth))
+        .collect()
+}
+
+/// Walker of a folder, skipping the directories of the no walk list and the
+/// extract folders of the given extract template
+fn walk_builder<P: AsRef<Path> + ?Sized>(
+    path: &P,
+    no_walk: NoWalkList,
+    extract_template: Option<String>,
+) -> WalkBuilder {
+    let mut builder = WalkBuilder::new(path);
+    builder
+        .filter_entry(move |entry| {
+            if let Some(ep) = entry.path().to_str() {
+                if no_walk.ignores.iter().any(|item| item.is_match(ep)) {
+                    return false;
+                }
+            }
+            if let Some(extract_template) = &extract_template {
+                if entry.depth() > 0 && is_extract_folder(entry.path(), extract_template) {
+                    return false;
+                }
+            }
+            true
+        })
+        .hidden(false)
+        .git_ignore(true);
+    builder
+}
+
+/// Check if the path is the extract folder of the archive file next to it
+fn is_extract_folder(path: &Path, extract_template: &str) -> bool {
+    let Some((prefix, suffix)) = extract_template.split_once("{FILENAME}") else {
+        return false;
+    };
+    path.file_name()
+        .and_then(std::ffi::OsStr::to_str)
+        .and_then(|name| name.strip_prefix(prefix)?.strip_suffix(suffix))
+        .is_some_and(|file_name| path.with_file_name(file_name).is_file())
+}
+
+/// Check if the walked entry is an archive file
+fn detect_archive(
+    result: Result<DirEntry, ignore::Error>,
+    detection: DetectionMode,
+    depth: u32,
+) -> Option<WalkResult> {
+    let de = result.ok()?;
+    let metadata = match de.metadata() {
+        Ok(m) => m,
+        Err(e) => {
+            log::info!("could not get dir entry medatada. {}", e);
+            return None;
+        }
+    };
+
+    if metadata.is_dir() {
+        return None;
+    }
+
+    // check if the file is comppreesed file
+    let path_buf = de.path().to_path_buf();
+    ArchiveKind::detect(&path_buf, detection).map(|archive_kind| WalkResult {
+        archive_kind,
+        virtual_path: VirtualPath::new(&path_buf),
+        path_buf,
+        depth,
+        data: None,
+        spilled: None,
+        containers: vec![],
+    })
+}
+
+/// Status of a [`WalkResult`] which was not unpacked yet
+fn new_status(archive_path: &WalkResult) -> UnpackStatus {
+    UnpackStatus {
+        archive_file: archive_path.path_buf.clone(),
+        virtual_path: archive_path.virtual_path.clone(),
+        kind: archive_path.archive_kind,
+        depth: archive_path.depth,
+        parent: archive_path.virtual_path.parent(),
+        extract_to: None,
+        err: None,
+        rewritten_entries: vec![],
+        limit_exceeded: None,
+        duplicate_of: None,
+        canonical_extract_to: None,
+        cached: false,
+        entries_extracted: 0,
+        entries_skipped: 0,
+        compressed_bytes: match &archive_path.data {
+            Some(data) => data.len() as u64,
+            None => fs::metadata(archive_path.file()).map_or(0, |m| m.len()),
+        },
+        uncompressed_bytes: 0,
+        elapsed: Duration::ZERO,
     }
 }
 
+/// Node of the provenance tree for a [`WalkResult`], without its children
+fn archive_node(archive_path: &WalkResult, extract_to: Option<PathBuf>) -> ArchiveNode {
+    ArchiveNode {
+        archive_file: archive_path.path_buf.clone(),
+        virtual_path: archive_path.virtual_path.clone(),
+        kind: archive_path.archive_kind,
+        depth: archive_path.depth,
+        parent: archive_path.virtual_path.parent(),
+        extract_to,
+        children: vec![],
+    }
+}
+
+/// Attach every archive to the archive it was found in, returns the archives
+/// of the folder
+fn provenance_tree(mut archives: Vec<ArchiveNode>) -> Vec<ArchiveNode> {
+    archives.sort_by(|a, b| a.virtual_path.cmp(&b.virtual_path));
+    let mut nodes = HashMap::new();
+    let mut order = vec![];
+    for archive in archives {
+        order.push((archive.depth, archive.virtual_path.clone()));
+        nodes.insert(archive.virtual_path.clone(), archive);
+    }
+
+    // the deepest archives first, a node is complete before it is attached.
+    // the sort is stable, the children stay in virtual path order
+    order.sort_by_key(|(depth, _)| Reverse(*depth));
+    let mut roots = vec![];
+    for (_, virtual_path) in order {
+        let Some(node) = nodes.remove(&virtual_path) else {
+            continue;
+        };
+        match node
+            .parent
+            .as_ref()
+            .and_then(|parent| nodes.get_mut(parent))
+        {
+            Some(parent) => parent.children.push(node),
+            None => roots.push(node),
+        }
+    }
+    roots
+}
+
+/// Lock the mutex, the data of a panicked task is still valid
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Hash the content of the file
+fn content_hash(path: &Path) -> Result<ContentHash> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().into())
+}
+
+/// Link the extract folder of a duplicate to the extract folder of its first
+/// copy
+fn link_folder(canonical: &Path, link: &Path, mode: DuplicateMode) -> io::Result<()> {
+    if let Some(parent) = link.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // the link of a previous run to the same folder
+    match fs::symlink_metadata(link) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(link)?,
+        Ok(_) => fs::remove_file(link)?,
+        Err(_) => {}
+    }
+    match mode {
+        DuplicateMode::Unpack | DuplicateMode::Record => Ok(()),
+        DuplicateMode::Hardlink => hard_link_tree(canonical, link),
+        // the link is resolved from its own folder
+        DuplicateMode::Symlink => symlink_dir(&fs::canonicalize(canonical)?, link),
+    }
+}
+
+/// Mirror the folder with hard links to its files
+fn hard_link_tree(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            hard_link_tree(&entry.path(), &target)?;
+        } else {
+            fs::hard_link(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn symlink_dir(original: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(original, link)
+}
+
+#[cfg(windows)]
+fn symlink_dir(original: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_dir(original, link)
+}
+
 /// Split path to file name and parant path
 fn split_file_by_name(path: &Path) -> Result<(String, PathBuf)> {
     let file_name = path
@@ -0,0 +1,187 @@
+//! Paths of nested archive members, e.g. `app/a.zip!/lib/b.jar!/c.properties`
+use std::{
+    convert::Infallible,
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Separator between a container and the path of a member inside it. A `!`
+/// of a file or member name which would read as a part of a separator (before
+/// a `/`, another `!` or at the end of the name) is doubled.
+pub const SEPARATOR: &str = "!/";
+
+/// The chain of containers of a file: the file on disk, then the path of each
+/// member inside the previous container
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct VirtualPath {
+    file: PathBuf,
+    members: Vec<String>,
+}
+
+impl VirtualPath {
+    /// Virtual path of a file on disk
+    #[must_use]
+    pub fn new<P: Into<PathBuf>>(file: P) -> Self {
+        Self {
+            file: file.into(),
+            members: vec![],
+        }
+    }
+
+    /// The file on disk the chain starts with
+    #[must_use]
+    pub fn file(&self) -> &Path {
+        &self.file
+    }
+
+    /// Path of each member inside the previous container, `/` separated
+    #[must_use]
+    pub fn members(&self) -> &[String] {
+        &self.members
+    }
+
+    /// Virtual path of the container of this member, `None` for a file on
+    /// disk
+    #[must_use]
+    pub fn parent(&self) -> Option<Self> {
+        let (_, members) = self.members.split_last()?;
+        Some(Self {
+            file: self.file.clone(),
+            members: members.to_vec(),
+        })
+    }
+
+    /// Virtual path of a member of this container
+    #[must_use]
+    pub fn join<P: AsRef<Path>>(&self, member: P) -> Self {
+        let member = member
+            .as_ref()
+            .iter()
+            .map(|c| c.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let mut members = self.members.clone();
+        members.push(member);
+        Self {
+            file: self.file.clone(),
+            members,
+        }
+    }
+
+    /// Check if this is a member of the container, at any nesting level
+    #[must_use]
+    pub fn is_inside(&self, container: &Self) -> bool {
+        self.file == container.file
+            && self.members.len() > container.members.len()
+            && self.members.starts_with(&container.members)
+    }
+}
+
+impl fmt::Display for VirtualPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", escape(&self.file.display().to_string()))?;
+        for member in &self.members {
+            write!(f, "{}{}", SEPARATOR, escape(member))?;
+        }
+        Ok(())
+    }
+}
+
+/// Double the `!` of a name which would read as a part of a separator
+fn escape(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    let mut chars = name.chars().peekable();
+    while let Some(c) = chars.next() {
+        escaped.push(c);
+        if c == '!' && matches!(chars.peek(), None | Some('!' | '/')) {
+            escaped.push('!');
+        }
+    }
+    escaped
+}
+
+/// Serialized as its display string
+impl Serialize for VirtualPath {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Deserialized from its display string
+impl<'de> Deserialize<'de> for VirtualPath {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Ok(s.parse().unwrap_or_else(|e: Infallible| match e {}))
+    }
+}
+
+impl FromStr for VirtualPath {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = vec![];
+        let mut part = String::new();
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            match (c, chars.peek()) {
+                ('!', Some('!')) => {
+                    chars.next();
+                    part.push('!');
+                }
+                ('!', Some('/')) => {
+                    chars.next();
+                    parts.push(std::mem::take(&mut part));
+                }
+                _ => part.push(c),
+            }
+        }
+        parts.push(part);
+        let mut parts = parts.into_iter();
+        Ok(Self {
+            file: PathBuf::from(parts.next().unwrap_or_default()),
+            members: parts.collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test_virtual_path {
+
+    use insta::assert_debug_snapshot;
+
+    use super::*;
+
+    #[test]
+    fn can_display_and_parse() {
+        let path = VirtualPath::new("app/a.zip")
+            .join(Path::new("lib").join("b.jar"))
+            .join("c.properties");
+        let display = path.to_string();
+
+        assert_eq!(display.parse::<VirtualPath>().unwrap(), path);
+        assert_eq!(
+            path.parent().and_then(|p| p.parent()),
+            Some(VirtualPath::new("app/a.zip"))
+        );
+        assert_eq!(VirtualPath::new("app/a.zip").parent(), None);
+        assert_debug_snapshot!((display, path));
+    }
+
+    #[test]
+    fn can_display_and_parse_names_with_bang() {
+        let path = VirtualPath::new("app!/a!.zip")
+            .join("lib!!/b!.jar")
+            .join("c!d.properties!");
+        let display = path.to_string();
+
+        assert_eq!(display, "app!!/a!.zip!/lib!!!!/b!.jar!/c!d.properties!!");
+        assert_eq!(display.parse::<VirtualPath>().unwrap(), path);
+        assert_eq!(
+            "app/a.zip!/c!d.txt".parse::<VirtualPath>().unwrap(),
+            VirtualPath::new("app/a.zip").join("c!d.txt")
+        );
+    }
+}
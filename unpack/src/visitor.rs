@@ -0,0 +1,225 @@
+//! Visiting the archive entries instead of writing them to the disk
+use std::{
+    io::{self, Read},
+    sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender},
+    thread::{self, JoinHandle},
+};
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+use crate::{
+    data::{UnpackResult, UnpackStatus},
+    error::RunError,
+    formats::kinds::ArchiveKind,
+    unpack::DeepWalk,
+    virtual_path::VirtualPath,
+};
+
+/// Metadata of an archive entry given to [`Visitor::on_entry`]
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct EntryMetadata {
+    /// virtual path of the archive the entry belongs to
+    pub archive: VirtualPath,
+    /// unpack level of the archive, archives of the root path are level 1
+    pub depth: u32,
+}
+
+/// Receives the entries of the archives found under the folder when
+/// unpacking with `DeepWalk::new().visit(..)`, nothing is extracted to the
+/// disk.
+///
+/// A [`VirtualPath`] is the path of the root archive followed by the entry
+/// names of the nested archives (e.g. `app/a.zip!/lib/b.jar!/config.yaml`).
+/// The calls of a root archive are made in order, `on_archive_enter` and
+/// `on_archive_leave` wrapping the calls of the archive and its nested
+/// archives. Root archives are visited in parallel.
+pub trait Visitor: Sync {
+    /// A file entry which is not unpacked further, `reader` streams its
+    /// content. A nested archive which could not be unpacked is given as an
+    /// entry of its container after its `on_archive_leave`.
+    ///
+    /// # Errors
+    /// An error stops the unpacking of the archive, it is reported in its
+    /// [`UnpackStatus`] as [`UnpackError::Visitor`](crate::UnpackError::Visitor)
+    fn on_entry(
+        &self,
+        virtual_path: &VirtualPath,
+        metadata: &EntryMetadata,
+        reader: &mut dyn Read,
+    ) -> Result<()>;
+
+    /// The archive is about to be unpacked
+    fn on_archive_enter(&self, _virtual_path: &VirtualPath, _kind: ArchiveKind) {}
+
+    /// The archive and its nested archives were unpacked
+    fn on_archive_leave(&self, _virtual_path: &VirtualPath, _status: &UnpackStatus) {}
+}
+
+/// A file entry yielded by [`Entries`]: its virtual path, metadata and content
+pub type Entry = (VirtualPath, EntryMetadata, Box<dyn Read + Send>);
+
+/// Maximum size of a chunk of entry content sent to [`Entries`]
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Request of an [`EntryReader`] to the background unpacking
+enum Request {
+    /// The next chunk of the content, up to the given size
+    Read(usize),
+    /// Stop reading the entry, the iteration goes on
+    Skip,
+}
+
+/// Content of an entry yielded by [`Entries`], read from the background
+/// unpacking one chunk at a time
+struct EntryReader {
+    requests: Sender<Request>,
+    chunks: Receiver<io::Result<Vec<u8>>>,
+    /// the end of the content was read
+    done: bool,
+}
+
+impl Read for EntryReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.done || buf.is_empty() {
+            return Ok(0);
+        }
+        let skipped = || io::Error::other("the entry was skipped by the entries iterator");
+        self.requests
+            .send(Request::Read(buf.len().min(CHUNK_SIZE)))
+            .map_err(|_| skipped())?;
+        let chunk = self.chunks.recv().map_err(|_| skipped())??;
+        buf[..chunk.len()].copy_from_slice(&chunk);
+        self.done = chunk.is_empty();
+        Ok(chunk.len())
+    }
+}
+
+/// Sends the visited entries to [`Entries`], one at a time, with the sender
+/// of the requests of their reader
+struct ChannelVisitor {
+    tx: SyncSender<(Entry, Sender<Request>)>,
+}
+
+impl Visitor for ChannelVisitor {
+    fn on_entry(
+        &self,
+        virtual_path: &VirtualPath,
+        metadata: &EntryMetadata,
+        reader: &mut dyn Read,
+    ) -> Result<()> {
+        let (requests_tx, requests) = channel();
+        let (chunks, chunks_rx) = channel();
+        let entry_reader = EntryReader {
+            requests: requests_tx.clone(),
+            chunks: chunks_rx,
+            done: false,
+        };
+        self.tx
+            .send((
+                (
+                    virtual_path.clone(),
+                    metadata.clone(),
+                    Box::new(entry_reader),
+                ),
+                requests_tx,
+            ))
+            .map_err(|_| anyhow!("the entries iterator was dropped"))?;
+
+        // the content is read while the reader asks for it, until the end or
+        // until the iterator goes on
+        let mut buf = vec![0_u8; CHUNK_SIZE];
+        while let Ok(Request::Read(len)) = requests.recv() {
+            match reader.read(&mut buf[..len]) {
+                Ok(read) => {
+                    // the reader may be gone, the rest is skipped by the next
+                    // request
+                    let _ = chunks.send(Ok(buf[..read].to_vec()));
+                    if read == 0 {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    let _ = chunks.send(Err(io::Error::new(e.kind(), e.to_string())));
+                    return Err(e.into());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Iterator over the file entries of the archives found under the folder,
+/// created with `DeepWalk::new().entries()`.
+///
+/// The archives are visited in the background, an entry is read only when
+/// the previous one was taken, so stopping early skips the rest of the work.
+/// The content of an entry is streamed one chunk at a time while it is read,
+/// until the next entry is taken: the rest of the entry is then skipped and
+/// its reader fails. Dropping the iterator stops the background unpacking
+/// and waits for it.
+pub struct Entries {
+    rx: Option<Receiver<(Entry, Sender<Request>)>>,
+    /// the requests of the reader of the last entry
+    current: Option<Sender<Request>>,
+    handle: Option<JoinHandle<UnpackResult>>,
+}
+
+impl Entries {
+    pub(crate) fn new(config: DeepWalk) -> Self {
+        // a rendezvous channel, the next entry waits for the iterator
+        let (tx, rx) = sync_channel(0);
+        let handle = thread::spawn(move || config.run(Some(&ChannelVisitor { tx }), None));
+        Self {
+            rx: Some(rx),
+            current: None,
+            handle: Some(handle),
+        }
+    }
+
+    /// Stop the iteration and return the status of the archives. The archives
+    /// which were not visited yet are reported with an error.
+    ///
+    /// # Errors
+    /// [`RunError::Panicked`] when the background unpacking panicked
+    pub fn finish(mut self) -> Result<UnpackResult, RunError> {
+        match self.stop() {
+            Some(Ok(result)) => Ok(result),
+            _ => Err(RunError::Panicked),
+        }
+    }
+
+    /// Skip the rest of the last entry, so the background unpacking goes on
+    fn skip_current(&mut self) {
+        if let Some(current) = self.current.take() {
+            // the entry may be read to its end already
+            let _ = current.send(Request::Skip);
+        }
+    }
+
+    /// Stop the background unpacking and wait for it, `None` when it was
+    /// already stopped
+    fn stop(&mut self) -> Option<thread::Result<UnpackResult>> {
+        self.skip_current();
+        drop(self.rx.take());
+        self.handle.take().map(JoinHandle::join)
+    }
+}
+
+impl Iterator for Entries {
+    type Item = Entry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.skip_current();
+        let (entry, requests) = self.rx.as_ref()?.recv().ok()?;
+        self.current = Some(requests);
+        Some(entry)
+    }
+}
+
+impl Drop for Entries {
+    fn drop(&mut self) {
+        // a panic is only reported by `finish`
+        let _ = self.stop();
+    }
+}
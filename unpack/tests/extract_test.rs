@@ -1,13 +1,19 @@
 use std::{
     env::temp_dir,
     fs,
+    io::{self, Read},
     path::{Path, PathBuf},
-    sync::mpsc,
+    sync::{mpsc, Mutex},
+    time::Duration,
 };
 
-use deep_unpack::{ArchiveKind, DeepWalk, NoWalkList};
+use deep_unpack::{
+    ArchiveKind, Cache, DeepWalk, DetectionMode, DuplicateMode, EntryMetadata, Limits, Manifest,
+    ManifestHashes, NoWalkList, PathPolicy, ReportFormat, ReportWriter, UnpackLevel, UnpackStatus,
+    VirtualPath, Visitor,
+};
 use ignore::WalkBuilder;
-use insta::{assert_debug_snapshot, with_settings};
+use insta::{assert_debug_snapshot, assert_snapshot, with_settings};
 use regex::Regex;
 use rstest::rstest;
 use uuid::Uuid;
@@ -34,7 +40,7 @@ fn get_files_from_folder<P: AsRef<Path>>(path: &P) -> Vec<String> {
         })
     });
     {
-        let mut result = rx.iter().into_iter().collect::<Vec<String>>();
+        let mut result = rx.iter().collect::<Vec<String>>();
         result.sort();
         result
     }
@@ -65,23 +71,446 @@ fn test_can_extract_with_unpack_level(#[case] unpack_level: u32) {
             .unpack_folder(format!("{}", destination_folder.join("dest").display()))
             .unpack_level(unpack_level)
             .extract()
-            .unwrap();
+            .unwrap()
+            .statuses;
+
+        r.sort_by(|a, b| a.archive_file.cmp(&b.archive_file));
+        r
+    };
+
+    with_settings!({filters => vec![
+        (r"elapsed: [0-9.]+[a-zµ]+", "elapsed: [ELAPSED]"),
+        (r"//*.+/(dest)", "[DYNAMIC-PATH]"),
+        (r"([C]?:\\.+dest\\\\)", "[DYNAMIC-PATH]/"),// for windows
+        (r"\\\\", "/"), // for windows
+    ]}, {
+        assert_debug_snapshot!(get_files_from_folder(&destination_folder));
+        assert_debug_snapshot!(results);
+    });
+    fs::remove_dir_all(destination_folder).unwrap();
+}
+
+#[rstest]
+#[case("deep", UnpackLevel::Depth(2))]
+#[case("deep", UnpackLevel::Exhausted)]
+#[case("cycle", UnpackLevel::Exhausted)]
+fn test_can_extract_until_exhausted(#[case] folder: &str, #[case] unpack_level: UnpackLevel) {
+    set_snapshot_suffix!("[{}]-[{:?}]", folder, unpack_level);
+
+    let destination_folder = get_temp_dir();
+    let path = Path::new("tests").join("mocks").join(folder);
+
+    let result = DeepWalk::new()
+        .folder(format!("{}", path.display()))
+        .unpack_folder(format!("{}", destination_folder.join("dest").display()))
+        .unpack_level(unpack_level)
+        .extract()
+        .unwrap();
 
+    let results = {
+        let mut r = result.statuses;
         r.sort_by(|a, b| a.archive_file.cmp(&b.archive_file));
         r
     };
 
     with_settings!({filters => vec![
+        (r"elapsed: [0-9.]+[a-zµ]+", "elapsed: [ELAPSED]"),
         (r"//*.+/(dest)", "[DYNAMIC-PATH]"),
         (r"([C]?:\\.+dest\\\\)", "[DYNAMIC-PATH]/"),// for windows
         (r"\\\\", "/"), // for windows
     ]}, {
         assert_debug_snapshot!(get_files_from_folder(&destination_folder));
         assert_debug_snapshot!(results);
+        assert_debug_snapshot!(result.max_depth);
+    });
+    fs::remove_dir_all(destination_folder).unwrap();
+}
+
+#[rstest]
+#[case("all", 1024 * 1024)]
+#[case("small", 256)]
+fn test_can_extract_in_memory(#[case] name: &str, #[case] in_memory_limit: u64) {
+    set_snapshot_suffix!("[in-memory]-[{}]", name);
+
+    let destination_folder = get_temp_dir();
+    let path = Path::new("tests").join("mocks").join("deep");
+
+    let results = {
+        let mut r = DeepWalk::new()
+            .folder(format!("{}", path.display()))
+            .unpack_folder(format!("{}", destination_folder.join("dest").display()))
+            .unpack_level(UnpackLevel::Exhausted)
+            .in_memory_limit(in_memory_limit)
+            .extract()
+            .unwrap()
+            .statuses;
+
+        r.sort_by(|a, b| a.archive_file.cmp(&b.archive_file));
+        r
+    };
+
+    with_settings!({filters => vec![
+        (r"elapsed: [0-9.]+[a-zµ]+", "elapsed: [ELAPSED]"),
+        (r"//*.+/(dest)", "[DYNAMIC-PATH]"),
+        (r"([C]?:\\.+dest\\\\)", "[DYNAMIC-PATH]/"),// for windows
+        (r"\\\\", "/"), // for windows
+    ]}, {
+        assert_debug_snapshot!(get_files_from_folder(&destination_folder));
+        assert_debug_snapshot!(results);
+    });
+    fs::remove_dir_all(destination_folder).unwrap();
+}
+
+/// Records the visitor calls
+#[derive(Default)]
+struct RecordVisitor {
+    calls: Mutex<Vec<String>>,
+}
+
+impl Visitor for RecordVisitor {
+    fn on_entry(
+        &self,
+        virtual_path: &VirtualPath,
+        metadata: &EntryMetadata,
+        reader: &mut dyn Read,
+    ) -> anyhow::Result<()> {
+        let bytes = io::copy(reader, &mut io::sink())?;
+        self.calls.lock().unwrap().push(format!(
+            "entry {} ({} bytes, level {})",
+            virtual_path, bytes, metadata.depth
+        ));
+        Ok(())
+    }
+
+    fn on_archive_enter(&self, virtual_path: &VirtualPath, kind: ArchiveKind) {
+        self.calls
+            .lock()
+            .unwrap()
+            .push(format!("enter {} ({:?})", virtual_path, kind));
+    }
+
+    fn on_archive_leave(&self, virtual_path: &VirtualPath, status: &UnpackStatus) {
+        self.calls
+            .lock()
+            .unwrap()
+            .push(format!("leave {} ({:?})", virtual_path, status.err));
+    }
+}
+
+#[test]
+fn test_can_keep_misnamed_archive_in_memory() {
+    let destination_folder = get_temp_dir();
+    let path = Path::new("tests").join("mocks").join("misnamed");
+
+    let statuses = DeepWalk::new()
+        .folder(format!("{}", path.display()))
+        .unpack_folder(format!("{}", destination_folder.join("dest").display()))
+        .unpack_level(2_u32)
+        .in_memory_limit(1024 * 1024_u64)
+        .extract()
+        .unwrap()
+        .statuses;
+    let visitor = RecordVisitor::default();
+    DeepWalk::new()
+        .folder(format!("{}", path.display()))
+        .unpack_level(2_u32)
+        .visit(&visitor)
+        .unwrap();
+
+    // the inner.zip which could not be unpacked is written as a file
+    let inner = statuses.iter().find(|status| status.depth == 2).unwrap();
+    assert!(inner.err.is_some());
+    assert_eq!(
+        fs::read_to_string(&inner.archive_file).unwrap(),
+        "SECRET=1\n"
+    );
+    with_settings!({filters => vec![
+        (r"\\\\", "/"), // for windows
+    ]}, {
+        assert_debug_snapshot!(visitor.calls.into_inner().unwrap());
+    });
+    fs::remove_dir_all(destination_folder).unwrap();
+}
+
+#[rstest]
+#[case("deep", UnpackLevel::Exhausted)]
+#[case("deep", UnpackLevel::Depth(2))]
+#[cfg_attr(feature = "rar", case("rar-volumes", UnpackLevel::Depth(1)))]
+#[cfg_attr(feature = "rar", case("rar-nested", UnpackLevel::Depth(2)))]
+fn test_can_visit(#[case] folder: &str, #[case] unpack_level: UnpackLevel) {
+    set_snapshot_suffix!("[{}]-[{:?}]", folder, unpack_level);
+
+    let destination_folder = get_temp_dir();
+    let path = Path::new("tests").join("mocks").join(folder);
+
+    let visitor = RecordVisitor::default();
+    DeepWalk::new()
+        .folder(format!("{}", path.display()))
+        .unpack_folder(format!("{}", destination_folder.join("dest").display()))
+        .unpack_level(unpack_level)
+        .visit(&visitor)
+        .unwrap();
+
+    // nothing is written
+    assert!(get_files_from_folder(&destination_folder).is_empty());
+    with_settings!({filters => vec![
+        (r"\\\\", "/"), // for windows
+    ]}, {
+        assert_debug_snapshot!(visitor.calls.into_inner().unwrap());
+    });
+    fs::remove_dir_all(destination_folder).unwrap();
+}
+
+#[test]
+fn test_can_visit_archives_larger_than_in_memory_limit() {
+    let path = Path::new("tests").join("mocks").join("deep");
+    let visit = |in_memory_limit: Option<u64>| {
+        let visitor = RecordVisitor::default();
+        DeepWalk::new()
+            .folder(format!("{}", path.display()))
+            .unpack_level(UnpackLevel::Exhausted)
+            .in_memory_limit(in_memory_limit)
+            .visit(&visitor)
+            .unwrap();
+        visitor.calls.into_inner().unwrap()
+    };
+
+    // the nested archives are unpacked from a temporary copy
+    assert_eq!(visit(Some(1)), visit(None));
+}
+
+#[test]
+fn test_can_visit_spilled_archives_with_limits() {
+    let path = Path::new("tests").join("mocks").join("deep");
+
+    let statuses = DeepWalk::new()
+        .folder(format!("{}", path.display()))
+        .unpack_level(UnpackLevel::Exhausted)
+        .in_memory_limit(Some(1))
+        .limits(Limits {
+            max_ratio: Some(50),
+            ..Limits::default()
+        })
+        .visit(&RecordVisitor::default())
+        .unwrap()
+        .statuses;
+
+    // the ratio of a spilled archive is the one of its temporary copy
+    assert_eq!(statuses.len(), 4);
+    for status in statuses {
+        assert!(
+            status.err.is_none(),
+            "{}: {:?}",
+            status.virtual_path,
+            status.err
+        );
+        assert!(status.compressed_bytes > 0);
+    }
+}
+
+#[test]
+fn test_can_iterate_entries() {
+    let path = Path::new("tests").join("mocks").join("deep");
+
+    let entries = DeepWalk::new()
+        .folder(format!("{}", path.display()))
+        .unpack_level(UnpackLevel::Exhausted)
+        .entries()
+        .unwrap()
+        .map(|(virtual_path, metadata, mut reader)| {
+            let mut content = String::new();
+            reader.read_to_string(&mut content).unwrap();
+            (virtual_path.to_string(), metadata.depth, content)
+        })
+        .collect::<Vec<_>>();
+
+    with_settings!({filters => vec![
+        (r"\\\\", "/"), // for windows
+    ]}, {
+        assert_debug_snapshot!(entries);
+    });
+}
+
+#[cfg(feature = "rar")]
+#[test]
+fn test_can_skip_entries_left_unread() {
+    let path = Path::new("tests").join("mocks").join("rar-volumes");
+
+    let mut entries = DeepWalk::new()
+        .folder(format!("{}", path.display()))
+        .entries()
+        .unwrap();
+    let (_, _, mut first) = entries.next().unwrap();
+    let (second, _, mut reader) = entries.next().unwrap();
+    let mut content = String::new();
+    reader.read_to_string(&mut content).unwrap();
+
+    // the first entry was skipped when the iteration went on
+    assert!(first.read(&mut [0_u8; 8]).is_err());
+    assert!(second.to_string().ends_with("marrowdale.txt"));
+    assert_eq!(content.len(), 29);
+    // dropping the iterator stops the background unpacking
+    drop(entries);
+}
+
+#[cfg(feature = "rar")]
+#[test]
+fn test_can_stop_entries_early() {
+    let path = Path::new("tests").join("mocks").join("rar-volumes");
+
+    let mut entries = DeepWalk::new()
+        .folder(format!("{}", path.display()))
+        .entries()
+        .unwrap();
+    let first = entries
+        .next()
+        .map(|(virtual_path, _, _)| virtual_path.to_string());
+    let result = entries.finish().unwrap();
+
+    with_settings!({filters => vec![
+        (r"elapsed: [0-9.]+[a-zµ]+", "elapsed: [ELAPSED]"),
+        (r"\\\\", "/"), // for windows
+    ]}, {
+        assert_debug_snapshot!(first);
+        assert_debug_snapshot!(result.statuses);
+    });
+}
+
+#[rstest]
+#[case("deep", UnpackLevel::Exhausted)]
+#[case("multiple", UnpackLevel::Depth(2))]
+#[case("cycle", UnpackLevel::Exhausted)]
+fn test_can_build_provenance_tree(#[case] folder: &str, #[case] unpack_level: UnpackLevel) {
+    set_snapshot_suffix!("[tree]-[{}]-[{:?}]", folder, unpack_level);
+
+    let destination_folder = get_temp_dir();
+    let path = Path::new("tests").join("mocks").join(folder);
+
+    let result = DeepWalk::new()
+        .folder(format!("{}", path.display()))
+        .unpack_folder(format!("{}", destination_folder.join("dest").display()))
+        .unpack_level(unpack_level)
+        .extract()
+        .unwrap();
+
+    with_settings!({filters => vec![
+        (r"//*.+/(dest)", "[DYNAMIC-PATH]"),
+        (r"([C]?:\\.+dest\\\\)", "[DYNAMIC-PATH]/"),// for windows
+        (r"\\\\", "/"), // for windows
+    ]}, {
+        assert_debug_snapshot!(result.tree);
+    });
+    fs::remove_dir_all(destination_folder).unwrap();
+}
+
+#[rstest]
+#[case("deep", ReportFormat::JsonLines)]
+#[case("deep", ReportFormat::Json)]
+#[cfg_attr(feature = "rar", case("rar-unsupported", ReportFormat::JsonLines))]
+fn test_can_write_report(#[case] folder: &str, #[case] format: ReportFormat) {
+    set_snapshot_suffix!("[report]-[{}]-[{:?}]", folder, format);
+
+    let destination_folder = get_temp_dir();
+    let path = Path::new("tests").join("mocks").join(folder);
+
+    let report = ReportWriter::new(vec![], format);
+    let result = DeepWalk::new()
+        .folder(format!("{}", path.display()))
+        .unpack_folder(format!("{}", destination_folder.join("dest").display()))
+        .unpack_level(UnpackLevel::Exhausted)
+        .extract_with_report(&report)
+        .unwrap();
+    let report = String::from_utf8(report.finish(&result).unwrap()).unwrap();
+
+    with_settings!({filters => vec![
+        (r#""[^"]*/dest/"#, "\"[DYNAMIC-PATH]/"),
+        (r#""[C]?:[^"]*dest\\\\\\\\"#, "\"[DYNAMIC-PATH]/"),// for windows
+        (r#""elapsed": ?\{[^}]*\}"#, "\"elapsed\": \"[ELAPSED]\""),
+        (r"\\\\", "/"), // for windows
+    ]}, {
+        assert_snapshot!(report);
+    });
+    fs::remove_dir_all(destination_folder).unwrap();
+}
+
+#[rstest]
+#[case("deep", ManifestHashes::Sha256)]
+#[cfg_attr(feature = "rar", case("rar-volumes", ManifestHashes::Sha256Blake3))]
+fn test_can_write_manifest(#[case] folder: &str, #[case] hashes: ManifestHashes) {
+    set_snapshot_suffix!("[manifest]-[{}]-[{:?}]", folder, hashes);
+
+    let destination_folder = get_temp_dir();
+    let path = Path::new("tests").join("mocks").join(folder);
+    let unpack_folder = destination_folder.join("dest");
+
+    let result = DeepWalk::new()
+        .folder(format!("{}", path.display()))
+        .unpack_folder(format!("{}", unpack_folder.display()))
+        .unpack_level(UnpackLevel::Exhausted)
+        .manifest(hashes)
+        .extract()
+        .unwrap();
+
+    let manifest = result.manifest.unwrap();
+    let written = fs::read_to_string(Manifest::path(&unpack_folder)).unwrap();
+    for file in &manifest.files {
+        assert_eq!(fs::metadata(&file.path).unwrap().len(), file.size);
+        assert!(written.contains(&file.sha256));
+        assert!(file.mtime.is_some());
+        assert_eq!(file.mode.is_some(), cfg!(unix));
+    }
+
+    let files = manifest
+        .files
+        .iter()
+        .map(|file| {
+            (
+                file.virtual_path.to_string(),
+                file.size,
+                file.sha256.as_str(),
+                file.blake3.as_deref(),
+            )
+        })
+        .collect::<Vec<_>>();
+    with_settings!({filters => vec![
+        (r"\\", "/"), // for windows
+    ]}, {
+        assert_debug_snapshot!(files);
     });
     fs::remove_dir_all(destination_folder).unwrap();
 }
 
+#[test]
+fn test_can_map_virtual_paths() {
+    let destination_folder = get_temp_dir();
+    let path = Path::new("tests").join("mocks").join("deep");
+
+    let result = DeepWalk::new()
+        .folder(format!("{}", path.display()))
+        .unpack_folder(format!("{}", destination_folder.join("dest").display()))
+        .unpack_level(UnpackLevel::Exhausted)
+        .extract()
+        .unwrap();
+
+    let virtual_paths = get_files_from_folder(&destination_folder)
+        .into_iter()
+        .map(|file| {
+            let virtual_path = result.virtual_path(&file).map(|v| v.to_string());
+            (file, virtual_path)
+        })
+        .collect::<Vec<_>>();
+
+    with_settings!({filters => vec![
+        (r"//*.+/(dest)", "[DYNAMIC-PATH]"),
+        (r"([C]?:\\.+dest\\\\)", "[DYNAMIC-PATH]/"),// for windows
+        (r"\\\\", "/"), // for windows
+    ]}, {
+        assert_debug_snapshot!(virtual_paths);
+    });
+    assert_eq!(result.virtual_path(&path), None);
+    fs::remove_dir_all(destination_folder).unwrap();
+}
+
 #[test]
 fn test_can_extract_with_ignores() {
     let destination_folder = get_temp_dir();
@@ -99,13 +528,15 @@ fn test_can_extract_with_ignores() {
             .unpack_folder(format!("{}", destination_folder.join("dest").display()))
             .no_walk(no_walk)
             .extract()
-            .unwrap();
+            .unwrap()
+            .statuses;
 
         r.sort_by(|a, b| a.archive_file.cmp(&b.archive_file));
         r
     };
 
     with_settings!({filters => vec![
+        (r"elapsed: [0-9.]+[a-zµ]+", "elapsed: [ELAPSED]"),
             (r"//*.+/(dest)", "[DYNAMIC-PATH]"),
             (r"([C]?:\\.+dest\\\\)", "[DYNAMIC-PATH]/"),// for windows
             (r"\\\\", "/"), // for windows
@@ -125,16 +556,18 @@ fn test_can_extract_with_template() {
         let mut r = DeepWalk::new()
             .folder(format!("{}", path.display()))
             .unpack_folder(format!("{}", destination_folder.join("dest").display()))
-            .unpack_level(2 as u32)
+            .unpack_level(2_u32)
             .extract_template("_PREFIX_${FILENAME}$_SUFFIX_")
             .extract()
-            .unwrap();
+            .unwrap()
+            .statuses;
 
         r.sort_by(|a, b| a.archive_file.cmp(&b.archive_file));
         r
     };
 
     with_settings!({filters => vec![
+        (r"elapsed: [0-9.]+[a-zµ]+", "elapsed: [ELAPSED]"),
             (r"//*.+/(dest)", "[DYNAMIC-PATH]"),
             (r"([C]?:\\.+dest\\\\)", "[DYNAMIC-PATH]/"),// for windows
             (r"\\\\", "/"), // for windows
@@ -145,9 +578,582 @@ fn test_can_extract_with_template() {
     fs::remove_dir_all(destination_folder).unwrap();
 }
 
+#[rstest]
+#[case(DetectionMode::Extension)]
+#[case(DetectionMode::Content)]
+#[case(DetectionMode::Both)]
+fn test_can_extract_with_detection(#[case] detection: DetectionMode) {
+    set_snapshot_suffix!("[detection]-[{:?}]", detection);
+
+    let destination_folder = get_temp_dir();
+    let path = Path::new("tests").join("mocks").join("renamed");
+
+    let results = {
+        let mut r = DeepWalk::new()
+            .folder(format!("{}", path.display()))
+            .unpack_folder(format!("{}", destination_folder.join("dest").display()))
+            .detection(detection)
+            .extract()
+            .unwrap()
+            .statuses;
+
+        r.sort_by(|a, b| a.archive_file.cmp(&b.archive_file));
+        r
+    };
+
+    with_settings!({filters => vec![
+        (r"elapsed: [0-9.]+[a-zµ]+", "elapsed: [ELAPSED]"),
+        (r"//*.+/(dest)", "[DYNAMIC-PATH]"),
+        (r"([C]?:\\.+dest\\\\)", "[DYNAMIC-PATH]/"),// for windows
+        (r"\\\\", "/"), // for windows
+    ]}, {
+        assert_debug_snapshot!(get_files_from_folder(&destination_folder));
+        assert_debug_snapshot!(results);
+    });
+    fs::remove_dir_all(destination_folder).unwrap();
+}
+
+#[rstest]
+#[case(DetectionMode::Extension)]
+#[case(DetectionMode::Content)]
+fn test_can_extract_self_extracting(#[case] detection: DetectionMode) {
+    // without the rar feature the rar payload is reported as unsupported
+    let features = if cfg!(feature = "rar") {
+        ""
+    } else {
+        "-[no-rar]"
+    };
+    set_snapshot_suffix!("[detection]-[{:?}]{}", detection, features);
+
+    let destination_folder = get_temp_dir();
+    let path = Path::new("tests").join("mocks").join("sfx");
+
+    let results = {
+        let mut r = DeepWalk::new()
+            .folder(format!("{}", path.display()))
+            .unpack_folder(format!("{}", destination_folder.join("dest").display()))
+            .detection(detection)
+            .extract()
+            .unwrap()
+            .statuses;
+
+        r.sort_by(|a, b| a.archive_file.cmp(&b.archive_file));
+        r
+    };
+
+    with_settings!({filters => vec![
+        (r"elapsed: [0-9.]+[a-zµ]+", "elapsed: [ELAPSED]"),
+        (r"//*.+/(dest)", "[DYNAMIC-PATH]"),
+        (r"([C]?:\\.+dest\\\\)", "[DYNAMIC-PATH]/"),// for windows
+        (r"\\\\", "/"), // for windows
+    ]}, {
+        assert_debug_snapshot!(get_files_from_folder(&destination_folder));
+        assert_debug_snapshot!(results);
+    });
+    fs::remove_dir_all(destination_folder).unwrap();
+}
+
+#[rstest]
+#[case(PathPolicy::Reject)]
+#[case(PathPolicy::Sanitize)]
+#[case(PathPolicy::SkipEntry)]
+fn test_can_extract_with_path_policy(#[case] path_policy: PathPolicy) {
+    set_snapshot_suffix!("[path-policy]-[{:?}]", path_policy);
+
+    let destination_folder = get_temp_dir();
+    let path = Path::new("tests").join("mocks").join("zip-slip");
+
+    let results = {
+        let mut r = DeepWalk::new()
+            .folder(format!("{}", path.display()))
+            .unpack_folder(format!("{}", destination_folder.join("dest").display()))
+            .path_policy(path_policy)
+            .extract()
+            .unwrap()
+            .statuses;
+
+        r.sort_by(|a, b| a.archive_file.cmp(&b.archive_file));
+        r
+    };
+
+    with_settings!({filters => vec![
+        (r"elapsed: [0-9.]+[a-zµ]+", "elapsed: [ELAPSED]"),
+        (r"//*.+/(dest)", "[DYNAMIC-PATH]"),
+        (r"([C]?:\\.+dest\\\\)", "[DYNAMIC-PATH]/"),// for windows
+        (r"\\\\", "/"), // for windows
+    ]}, {
+        assert_debug_snapshot!(get_files_from_folder(&destination_folder));
+        assert_debug_snapshot!(results);
+    });
+    fs::remove_dir_all(destination_folder).unwrap();
+}
+
+#[rstest]
+#[case("unlimited", Limits::default())]
+#[case("archive-bytes", Limits { max_archive_bytes: Some(100_000), ..Limits::default() })]
+// every archive is stopped at its first write, whichever runs first
+#[case("total-bytes", Limits { max_total_bytes: Some(5), ..Limits::default() })]
+#[case("entries", Limits { max_entries: Some(2), ..Limits::default() })]
+#[case("ratio", Limits { max_ratio: Some(100), ..Limits::default() })]
+#[case("entry-bytes", Limits { max_entry_bytes: Some(4096), ..Limits::default() })]
+fn test_can_extract_with_limits(#[case] name: &str, #[case] limits: Limits) {
+    set_snapshot_suffix!("[limits]-[{}]", name);
+
+    let destination_folder = get_temp_dir();
+    let path = Path::new("tests").join("mocks").join("bomb");
+
+    let results = {
+        let mut r = DeepWalk::new()
+            .folder(format!("{}", path.display()))
+            .unpack_folder(format!("{}", destination_folder.join("dest").display()))
+            .limits(limits)
+            .extract()
+            .unwrap()
+            .statuses;
+
+        r.sort_by(|a, b| a.archive_file.cmp(&b.archive_file));
+        r
+    };
+
+    with_settings!({filters => vec![
+        (r"elapsed: [0-9.]+[a-zµ]+", "elapsed: [ELAPSED]"),
+        (r"//*.+/(dest)", "[DYNAMIC-PATH]"),
+        (r"([C]?:\\.+dest\\\\)", "[DYNAMIC-PATH]/"),// for windows
+        (r"\\\\", "/"), // for windows
+    ]}, {
+        assert_debug_snapshot!(get_files_from_folder(&destination_folder));
+        assert_debug_snapshot!(results);
+    });
+    fs::remove_dir_all(destination_folder).unwrap();
+}
+
+#[cfg(feature = "rar")]
+#[rstest]
+#[case("rar-volumes")]
+#[case("rar-unsupported")]
+fn test_can_extract_rar(#[case] folder: &str) {
+    set_snapshot_suffix!("[{}]", folder);
+
+    let destination_folder = get_temp_dir();
+    let path = Path::new("tests").join("mocks").join(folder);
+
+    let results = {
+        let mut r = DeepWalk::new()
+            .folder(format!("{}", path.display()))
+            .unpack_folder(format!("{}", destination_folder.join("dest").display()))
+            .extract()
+            .unwrap()
+            .statuses;
+
+        r.sort_by(|a, b| a.archive_file.cmp(&b.archive_file));
+        r
+    };
+
+    with_settings!({filters => vec![
+        (r"elapsed: [0-9.]+[a-zµ]+", "elapsed: [ELAPSED]"),
+        (r"//*.+/(dest)", "[DYNAMIC-PATH]"),
+        (r"([C]?:\\.+dest\\\\)", "[DYNAMIC-PATH]/"),// for windows
+        (r"\\\\", "/"), // for windows
+    ]}, {
+        assert_debug_snapshot!(get_files_from_folder(&destination_folder));
+        assert_debug_snapshot!(results);
+    });
+    fs::remove_dir_all(destination_folder).unwrap();
+}
+
+#[cfg(not(feature = "rar"))]
+#[test]
+fn test_can_report_rar_as_unsupported_without_feature() {
+    let destination_folder = get_temp_dir();
+    let path = Path::new("tests").join("mocks").join("rar-volumes");
+
+    let result = DeepWalk::new()
+        .folder(format!("{}", path.display()))
+        .unpack_folder(format!("{}", destination_folder.join("dest").display()))
+        .extract()
+        .unwrap();
+
+    assert_eq!(result.statuses.len(), 1);
+    assert!(matches!(
+        result.statuses[0].err,
+        Some(deep_unpack::UnpackError::Unsupported(_))
+    ));
+    fs::remove_dir_all(destination_folder).unwrap();
+}
+
+#[test]
+fn test_can_extract_with_total_limit_across_levels() {
+    let destination_folder = get_temp_dir();
+    let path = Path::new("tests").join("mocks").join("deep");
+
+    // level-1.zip writes 335 bytes, its level-2.jar crosses the limit
+    let results = {
+        let mut r = DeepWalk::new()
+            .folder(format!("{}", path.display()))
+            .unpack_folder(format!("{}", destination_folder.join("dest").display()))
+            .unpack_level(UnpackLevel::Exhausted)
+            .limits(Limits {
+                max_total_bytes: Some(400),
+                ..Limits::default()
+            })
+            .extract()
+            .unwrap()
+            .statuses;
+
+        r.sort_by(|a, b| a.archive_file.cmp(&b.archive_file));
+        r
+    };
+
+    with_settings!({filters => vec![
+        (r"elapsed: [0-9.]+[a-zµ]+", "elapsed: [ELAPSED]"),
+        (r"//*.+/(dest)", "[DYNAMIC-PATH]"),
+        (r"([C]?:\\.+dest\\\\)", "[DYNAMIC-PATH]/"),// for windows
+        (r"\\\\", "/"), // for windows
+    ]}, {
+        assert_debug_snapshot!(get_files_from_folder(&destination_folder));
+        assert_debug_snapshot!(results);
+    });
+    fs::remove_dir_all(destination_folder).unwrap();
+}
+
+#[test]
+fn test_can_extract_spring_boot_libs() {
+    let destination_folder = get_temp_dir();
+    let path = Path::new("tests").join("mocks").join("spring-boot");
+
+    let results = {
+        let mut r = DeepWalk::new()
+            .folder(format!("{}", path.display()))
+            .unpack_folder(format!("{}", destination_folder.join("dest").display()))
+            .unpack_level(2_u32)
+            .extract()
+            .unwrap()
+            .statuses;
+
+        r.sort_by(|a, b| a.archive_file.cmp(&b.archive_file));
+        r
+    };
+
+    with_settings!({filters => vec![
+        (r"elapsed: [0-9.]+[a-zµ]+", "elapsed: [ELAPSED]"),
+        (r"//*.+/(dest)", "[DYNAMIC-PATH]"),
+        (r"([C]?:\\.+dest\\\\)", "[DYNAMIC-PATH]/"),// for windows
+        (r"\\\\", "/"), // for windows
+    ]}, {
+        assert_debug_snapshot!(get_files_from_folder(&destination_folder));
+        assert_debug_snapshot!(results);
+    });
+    fs::remove_dir_all(destination_folder).unwrap();
+}
+
+#[test]
+fn test_can_extract_same_content_once() {
+    let destination_folder = get_temp_dir();
+    let path = Path::new("tests").join("mocks").join("cycle");
+
+    let results = {
+        let mut r = DeepWalk::new()
+            .folder(format!("{}", path.display()))
+            .unpack_folder(format!("{}", destination_folder.join("dest").display()))
+            .unpack_level(3_u32)
+            .extract()
+            .unwrap()
+            .statuses;
+
+        r.sort_by(|a, b| a.archive_file.cmp(&b.archive_file));
+        r
+    };
+
+    with_settings!({filters => vec![
+        (r"elapsed: [0-9.]+[a-zµ]+", "elapsed: [ELAPSED]"),
+        (r"//*.+/(dest)", "[DYNAMIC-PATH]"),
+        (r"([C]?:\\.+dest\\\\)", "[DYNAMIC-PATH]/"),// for windows
+        (r"\\\\", "/"), // for windows
+    ]}, {
+        assert_debug_snapshot!(get_files_from_folder(&destination_folder));
+        assert_debug_snapshot!(results);
+    });
+    fs::remove_dir_all(destination_folder).unwrap();
+}
+
+#[test]
+fn test_can_unpack_every_copy() {
+    let destination_folder = get_temp_dir();
+    let path = Path::new("tests").join("mocks").join("cycle");
+
+    let statuses = DeepWalk::new()
+        .folder(format!("{}", path.display()))
+        .unpack_folder(format!("{}", destination_folder.join("dest").display()))
+        .unpack_level(3_u32)
+        .duplicates(DuplicateMode::Unpack)
+        .extract()
+        .unwrap()
+        .statuses;
+
+    // nest.zip, nest.zip!/tern.zip, tern-copy.zip and tern.zip
+    assert_eq!(statuses.len(), 4);
+    assert!(statuses
+        .iter()
+        .all(|status| status.duplicate_of.is_none() && status.extract_to.is_some()));
+    fs::remove_dir_all(destination_folder).unwrap();
+}
+
+#[test]
+fn test_can_unpack_shallowest_copy() {
+    let destination_folder = get_temp_dir();
+    let path = Path::new("tests").join("mocks").join("nested-copies");
+    let summary = |statuses: Vec<UnpackStatus>| {
+        let mut summary = statuses
+            .into_iter()
+            .map(|status| {
+                (
+                    status.virtual_path.to_string(),
+                    status.duplicate_of,
+                    status.extract_to.is_some(),
+                )
+            })
+            .collect::<Vec<_>>();
+        summary.sort();
+        summary
+    };
+
+    // the visitor unpacks a.zip!/c.zip!/lib.jar before b.zip!/z.jar is found
+    let visited = DeepWalk::new()
+        .folder(format!("{}", path.display()))
+        .unpack_level(UnpackLevel::Exhausted)
+        .visit(&RecordVisitor::default())
+        .unwrap()
+        .statuses;
+    let extracted = DeepWalk::new()
+        .folder(format!("{}", path.display()))
+        .unpack_folder(format!("{}", destination_folder.join("dest").display()))
+        .unpack_level(UnpackLevel::Exhausted)
+        .extract()
+        .unwrap()
+        .statuses;
+
+    with_settings!({filters => vec![
+        (r"//*.+/(dest)", "[DYNAMIC-PATH]"),
+        (r"([C]?:\\.+dest\\\\)", "[DYNAMIC-PATH]/"),// for windows
+        (r"\\\\", "/"), // for windows
+    ]}, {
+        assert_debug_snapshot!(get_files_from_folder(&destination_folder));
+        assert_debug_snapshot!((summary(visited), summary(extracted)));
+    });
+    fs::remove_dir_all(destination_folder).unwrap();
+}
+
+#[rstest]
+#[case(DuplicateMode::Record)]
+#[case(DuplicateMode::Hardlink)]
+#[case(DuplicateMode::Symlink)]
+fn test_can_link_duplicates(#[case] mode: DuplicateMode) {
+    set_snapshot_suffix!("[duplicates]-[{:?}]", mode);
+
+    let destination_folder = get_temp_dir();
+    let path = Path::new("tests").join("mocks").join("cycle");
+
+    let results = {
+        let mut r = DeepWalk::new()
+            .folder(format!("{}", path.display()))
+            .unpack_folder(format!("{}", destination_folder.join("dest").display()))
+            .unpack_level(3_u32)
+            .duplicates(mode)
+            .extract()
+            .unwrap()
+            .statuses;
+
+        r.retain(|status| status.duplicate_of.is_some());
+        r.sort_by(|a, b| a.virtual_path.cmp(&b.virtual_path));
+        r
+    };
+
+    // the files seen through the links
+    let linked = results
+        .iter()
+        .filter_map(|status| status.extract_to.as_ref())
+        .map(|extract_to| {
+            let mut files = WalkBuilder::new(extract_to)
+                .follow_links(true)
+                .build()
+                .filter_map(Result::ok)
+                .filter(|de| de.path().is_file())
+                .map(|de| fs::read_to_string(de.path()).unwrap())
+                .collect::<Vec<_>>();
+            files.sort();
+            files
+        })
+        .collect::<Vec<_>>();
+
+    let results = results
+        .iter()
+        .map(|status| {
+            (
+                status.virtual_path.to_string(),
+                status.extract_to.clone(),
+                status.canonical_extract_to.clone(),
+                status.err.as_ref().map(ToString::to_string),
+            )
+        })
+        .collect::<Vec<_>>();
+    with_settings!({filters => vec![
+        (r"//*.+/(dest)", "[DYNAMIC-PATH]"),
+        (r"([C]?:\\.+dest\\\\)", "[DYNAMIC-PATH]/"),// for windows
+        (r"\\\\", "/"), // for windows
+    ]}, {
+        assert_debug_snapshot!((results, linked));
+    });
+    fs::remove_dir_all(destination_folder).unwrap();
+}
+
+#[test]
+fn test_can_reuse_cached_archives() {
+    let destination_folder = get_temp_dir();
+    let path = Path::new("tests").join("mocks").join("deep");
+    let cache_folder = destination_folder.join("cache");
+
+    let run = || {
+        let result = DeepWalk::new()
+            .folder(format!("{}", path.display()))
+            .unpack_folder(format!("{}", destination_folder.join("dest").display()))
+            .unpack_level(UnpackLevel::Exhausted)
+            .manifest(ManifestHashes::Sha256)
+            .cache_folder(format!("{}", cache_folder.display()))
+            .extract()
+            .unwrap();
+        let mut cached = result
+            .statuses
+            .iter()
+            .map(|status| (status.virtual_path.to_string(), status.cached))
+            .collect::<Vec<_>>();
+        cached.sort();
+        (cached, result.manifest.unwrap())
+    };
+
+    let (first, manifest) = run();
+    let (second, cached_manifest) = run();
+    assert_eq!(cached_manifest, manifest);
+
+    // a modified file invalidates the archive it was unpacked from
+    let modified = manifest
+        .files
+        .iter()
+        .find(|file| file.path.ends_with("marram.txt"))
+        .unwrap();
+    fs::write(&modified.path, "marram grass").unwrap();
+    let (third, _) = run();
+
+    let cache = Cache::new(&cache_folder);
+    assert_eq!(cache.prune(Duration::from_secs(24 * 60 * 60)).unwrap(), 0);
+    cache.clear().unwrap();
+    let (cleared, _) = run();
+
+    assert_debug_snapshot!((first, second, third, cleared));
+    fs::remove_dir_all(destination_folder).unwrap();
+}
+
+#[test]
+fn test_can_extract_deb_data() {
+    let destination_folder = get_temp_dir();
+    let path = Path::new("tests").join("mocks").join("deb");
+
+    let results = {
+        let mut r = DeepWalk::new()
+            .folder(format!("{}", path.display()))
+            .unpack_folder(format!("{}", destination_folder.join("dest").display()))
+            .unpack_level(2_u32)
+            .extract()
+            .unwrap()
+            .statuses;
+
+        r.sort_by(|a, b| a.archive_file.cmp(&b.archive_file));
+        r
+    };
+
+    with_settings!({filters => vec![
+        (r"elapsed: [0-9.]+[a-zµ]+", "elapsed: [ELAPSED]"),
+        (r"//*.+/(dest)", "[DYNAMIC-PATH]"),
+        (r"([C]?:\\.+dest\\\\)", "[DYNAMIC-PATH]/"),// for windows
+        (r"\\\\", "/"), // for windows
+    ]}, {
+        assert_debug_snapshot!(get_files_from_folder(&destination_folder));
+        assert_debug_snapshot!(results);
+    });
+    fs::remove_dir_all(destination_folder).unwrap();
+}
+
+#[test]
+fn test_can_extract_tar_from_compressed_stream() {
+    let destination_folder = get_temp_dir();
+    let path = Path::new("tests").join("mocks").join("compressed-tar");
+
+    let result = DeepWalk::new()
+        .folder(format!("{}", path.display()))
+        .unpack_folder(format!("{}", destination_folder.join("dest").display()))
+        .unpack_level(2_u32)
+        .manifest(ManifestHashes::Sha256)
+        .extract()
+        .unwrap();
+    // the manifest records the stream output under its `.tar` name
+    let manifest = result.manifest.unwrap();
+    assert!(manifest
+        .files
+        .iter()
+        .any(|file| file.path.ends_with("bundle.tar")));
+    assert!(manifest.files.iter().all(|file| file.path.is_file()));
+    let results = {
+        let mut r = result.statuses;
+        r.sort_by(|a, b| a.archive_file.cmp(&b.archive_file));
+        r
+    };
+
+    with_settings!({filters => vec![
+        (r"elapsed: [0-9.]+[a-zµ]+", "elapsed: [ELAPSED]"),
+        (r"//*.+/(dest)", "[DYNAMIC-PATH]"),
+        (r"([C]?:\\.+dest\\\\)", "[DYNAMIC-PATH]/"),// for windows
+        (r"\\\\", "/"), // for windows
+    ]}, {
+        assert_debug_snapshot!(get_files_from_folder(&destination_folder));
+        assert_debug_snapshot!(results);
+    });
+    fs::remove_dir_all(destination_folder).unwrap();
+}
+
 #[rstest]
 #[case("zip", "archive.zip")]
 #[case("jar", "archive.jar")]
+#[case("7z", "archive.7z")]
+#[cfg_attr(feature = "rar", case("rar", "archive.rar"))]
+#[cfg_attr(feature = "rar", case("rar", "archive-v4.rar"))]
+#[cfg_attr(feature = "rar", case("rar-compressed", "archive.rar"))]
+#[cfg_attr(feature = "rar", case("rar-compressed", "archive-v4.rar"))]
+#[cfg_attr(feature = "rar", case("rar-volumes", "archive.part1.rar"))]
+#[case("java", "app.war")]
+#[case("java", "app.ear")]
+#[case("java", "widget.aar")]
+#[case("java", "connector.rar")]
+#[case("java", "service.sar")]
+#[case("java", "plugin.hpi")]
+#[case("java", "bundle.kar")]
+#[case("java", "module.jmod")]
+#[case("spring-boot", "launcher.jar")]
+#[case("deb", "archive.deb")]
+#[case("cpio", "archive.cpio")]
+#[case("cpio", "archive-odc.cpio")]
+#[case("rpm", "archive.rpm")]
+#[case("tar", "archive.tar")]
+#[case("tar", "archive.tar.gz")]
+#[case("tar", "archive.tgz")]
+#[case("tar", "archive.tar.bz2")]
+#[case("tar", "archive.tar.xz")]
+#[case("tar", "archive.tar.zst")]
+#[case("compressed", "config.json.gz")]
+#[case("compressed", "config.json.bz2")]
+#[case("compressed", "config.json.xz")]
+#[case("compressed", "config.json.zst")]
+#[case("compressed", "config.json.lz4")]
+#[case("compressed", "config.json.lzma")]
+#[case("compressed", "config.json.Z")]
 fn test_can_extract_format(#[case] folder: &str, #[case] file: &str) {
     set_snapshot_suffix!("[{}]-[{}]", folder, file);
 